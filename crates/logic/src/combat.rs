@@ -1,22 +1,229 @@
 use bevy::{prelude::*, render::pipeline::RenderPipeline};
+use map_gen::enemies::{Enemy, EnemyAbility};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    map_graph::{MapDef, MapPosition, RoomEntity},
-    shapes::{self, ShapeMeshes},
+    map_graph::{
+        Coins, MapPosition, MoveQueue, PlayerPositionDisplay, RoomEntities, RoomEntity, RoomId,
+    },
+    replay::{GameTick, RecordedAction, RunRecording},
+    shapes::{CircleGaugeMaterial, ShapeMeshes},
+    text_feedback::TextFeedbackSpawn,
     AppState,
 };
 
 pub struct CombatPlugin;
 
-#[derive(Clone)]
+/// Enemy guarding a room, the player can't enter it before defeating it.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Battle {
+    pub name: String,
     pub hp: f32,
+    pub max_hp: f32,
     pub attack: f32,
+    /// Coins won by defeating the enemy.
+    pub reward: u32,
+    pub ability: Option<EnemyAbility>,
 }
+
+/// Enemy used when no enemy templates are defined.
+impl Default for Battle {
+    fn default() -> Self {
+        Self {
+            name: "Enemy".to_string(),
+            hp: 1f32,
+            max_hp: 1f32,
+            attack: 1f32,
+            reward: 0,
+            ability: None,
+        }
+    }
+}
+
+impl From<Enemy> for Battle {
+    fn from(enemy: Enemy) -> Self {
+        Self {
+            name: enemy.name,
+            hp: enemy.hp,
+            max_hp: enemy.hp,
+            attack: enemy.attack,
+            reward: enemy.reward,
+            ability: enemy.ability,
+        }
+    }
+}
+
+/// Health and fighting values of the player, for the current run.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlayerStats {
+    pub hp: f32,
+    pub max_hp: f32,
+    pub attack: f32,
+    /// Subtracted from the attack of enemies.
+    pub defence: f32,
+    /// Each one heals [`POTION_HEAL`] during a battle.
+    pub potions: u32,
+}
+
+pub const POTION_HEAL: f32 = 4f32;
+/// Multiplies the damage taken while defending.
+const DEFEND_DAMAGE_FACTOR: f32 = 0.5f32;
+
+impl Default for PlayerStats {
+    fn default() -> Self {
+        Self {
+            hp: 10f32,
+            max_hp: 10f32,
+            attack: 1f32,
+            defence: 0f32,
+            potions: 2,
+        }
+    }
+}
+
+impl PlayerStats {
+    pub fn is_dead(&self) -> bool {
+        self.hp <= 0f32
+    }
+}
+
+/// Choice of the player for one turn of an [`Encounter`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CombatAction {
+    Attack,
+    /// Halves the damage taken this turn.
+    Defend,
+    /// Drinks a potion.
+    UseItem,
+    /// Leaves without fighting, the enemy doesn't hit back.
+    Flee,
+}
+
+/// Present while the player fights the enemy of `room`, standing in it. Their moves wait
+/// for the end of the encounter, and the room effects for the enemy to be defeated.
+/// The encounter lasts a single exchange, see [`EncounterEnd`].
+pub struct Encounter {
+    pub room: RoomId,
+    /// Room the player came from, they are knocked back to it unless they win.
+    pub from: RoomId,
+    /// What happened so far, oldest first.
+    pub log: Vec<String>,
+}
+
+/// Added to the player display while it is pushed back to its room after a lost encounter.
+pub struct KnockBack;
+
+/// World units per second of the [`KnockBack`] animation.
+pub const KNOCK_BACK_SPEED: f32 = 150f32;
+
+/// Actions chosen in the encounter panel or replayed, resolved by `resolve_encounter`.
+#[derive(Default)]
+pub struct CombatInputs {
+    pub list: Vec<CombatAction>,
+}
+
+/// Result of one turn, see [`resolve_turn`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TurnOutcome {
+    pub damage_dealt: f32,
+    pub damage_taken: f32,
+    pub healed: f32,
+    /// The enemy was defeated this turn.
+    pub defeated: bool,
+}
+
+/// How an [`Encounter`] ends after its exchange.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EncounterEnd {
+    /// The enemy is defeated, the player stays in its room.
+    Won,
+    /// The enemy survived the turn or the player fled, the player is knocked back to the
+    /// room they came from.
+    KnockedBack,
+    /// The player died, the run is over.
+    Dead,
+}
+
+impl TurnOutcome {
+    pub fn encounter_end(&self, player: &PlayerStats) -> EncounterEnd {
+        if player.is_dead() {
+            EncounterEnd::Dead
+        } else if self.defeated {
+            EncounterEnd::Won
+        } else {
+            EncounterEnd::KnockedBack
+        }
+    }
+}
+
+/// The player acts first, then the enemy hits back if it survived.
+/// An enemy with [`EnemyAbility::FirstStrike`] hits first instead.
+pub fn resolve_turn(
+    action: CombatAction,
+    player: &mut PlayerStats,
+    battle: &mut Battle,
+) -> TurnOutcome {
+    let mut outcome = TurnOutcome::default();
+    if action == CombatAction::Flee {
+        return outcome;
+    }
+    let mut enemy_damage = (battle.attack - player.defence).max(0f32);
+    if action == CombatAction::Defend {
+        enemy_damage *= DEFEND_DAMAGE_FACTOR;
+    }
+    let first_strike = battle.ability == Some(EnemyAbility::FirstStrike);
+    if first_strike {
+        player.hp -= enemy_damage;
+        outcome.damage_taken = enemy_damage;
+        if player.is_dead() {
+            return outcome;
+        }
+    }
+    match action {
+        CombatAction::Attack => {
+            let blocked = match battle.ability {
+                Some(EnemyAbility::Armor(fraction)) => fraction.clamp(0f32, 1f32),
+                _ => 0f32,
+            };
+            outcome.damage_dealt = (player.attack * (1f32 - blocked)).min(battle.hp.max(0f32));
+            battle.hp -= outcome.damage_dealt;
+            if battle.hp <= 0f32 {
+                outcome.defeated = true;
+                return outcome;
+            }
+        }
+        CombatAction::UseItem if player.potions > 0 => {
+            player.potions -= 1;
+            outcome.healed = POTION_HEAL.min(player.max_hp - player.hp).max(0f32);
+            player.hp += outcome.healed;
+        }
+        _ => {}
+    }
+    if !first_strike {
+        player.hp -= enemy_damage;
+        outcome.damage_taken = enemy_damage;
+    }
+    if let Some(EnemyAbility::Regeneration(hp)) = battle.ability {
+        battle.hp = (battle.hp + hp).min(battle.max_hp);
+    }
+    outcome
+}
+
+/// On a room with a [`Battle`], points to the entity of its [`HealthGauge`].
 pub struct BattleGraphicRef {
     pub entity: Entity,
 }
 
+/// Circle gauge of the health of an enemy, each one has its own [`CircleGaugeMaterial`].
+pub struct HealthGauge {
+    pub battle: Entity,
+    /// Ratio shown, it follows `hp / max_hp` so hits are animated.
+    pub displayed: f32,
+}
+
+/// Ratio per second at which a [`HealthGauge`] catches up with the health of its enemy.
+const GAUGE_SPEED: f32 = 1.5f32;
+
 // TODO: prefer to use Changed<>
 pub struct IsDirty;
 
@@ -24,105 +231,345 @@ impl Plugin for CombatPlugin {
     fn build(&self, app: &mut AppBuilder) {
         let game_update_system_set = SystemSet::on_update(AppState::Game)
             .with_system(update_battle_room.system())
-            .with_system(react_to_will_move.system());
+            .with_system(animate_health_gauges.system())
+            .with_system(react_to_will_move.system())
+            .with_system(check_player_health.system());
         app.add_system_set(game_update_system_set);
+        app.add_system_set(
+            SystemSet::on_update(AppState::Game).with_system(
+                resolve_encounter
+                    .system()
+                    .label("combat_input")
+                    .after("replay_input"),
+            ),
+        );
+        app.insert_resource(PlayerStats::default());
+        app.insert_resource(CombatInputs::default());
     }
 }
 
+/// Moves the player, opening an [`Encounter`] when the room has an enemy.
 fn react_to_will_move(
     mut commands: Commands,
-    mut map: ResMut<MapDef>,
+    room_entities: Res<RoomEntities>,
     mut position_changed: ResMut<MapPosition>,
-    mut q_b: Query<(&mut Battle)>,
+    q_b: Query<&Battle>,
 ) {
-    // TODO: I guess we should move into the room and then go back rather than cancel the move...
     if !position_changed.is_changed() {
         return;
     }
     if position_changed.will_move.is_none() {
         return;
     }
-    let room_entity = map.rooms[&position_changed.will_move.unwrap()].entity;
-    let b = q_b.get_component_mut::<Battle>(room_entity);
-    if let Ok(mut b) = b {
-        if b.hp > 0f32 {
-            // No update of pos_id, because battle is still here
-            b.hp -= 1f32;
+    let target = position_changed.will_move.unwrap();
+    let room_entity = match room_entities.entities.get(&target) {
+        Some(e) => *e,
+        None => {
+            // The room was culled, the move is cancelled.
             position_changed.will_move = None;
-            commands.entity(room_entity).insert(IsDirty);
             return;
         }
+    };
+    if let Ok(b) = q_b.get(room_entity) {
+        if b.hp > 0f32 {
+            commands.insert_resource(Encounter {
+                room: target,
+                from: position_changed.pos_id,
+                log: vec![format!("{} blocks the way", b.name)],
+            });
+        }
     }
-    position_changed.pos_id = position_changed.will_move.unwrap();
+    position_changed.pos_id = target;
     position_changed.will_move = None;
 }
 
+/// Plays the first action of [`CombatInputs`] against the enemy of the [`Encounter`], the
+/// danger zones keep growing meanwhile. Unless the enemy is defeated, the player is knocked
+/// back to the room they came from.
+fn resolve_encounter(
+    mut commands: Commands,
+    tick: Res<GameTick>,
+    room_entities: Res<RoomEntities>,
+    encounter: Option<ResMut<Encounter>>,
+    mut inputs: ResMut<CombatInputs>,
+    mut player: ResMut<PlayerStats>,
+    mut coins: ResMut<Coins>,
+    mut position: ResMut<MapPosition>,
+    mut move_queue: ResMut<MoveQueue>,
+    mut recording: ResMut<RunRecording>,
+    mut q_b: Query<(&mut Battle, &RoomEntity)>,
+    q_player: Query<Entity, With<PlayerPositionDisplay>>,
+) {
+    let mut encounter = match encounter {
+        Some(encounter) => encounter,
+        None => {
+            inputs.list.clear();
+            return;
+        }
+    };
+    let room_entity = room_entities.entities.get(&encounter.room).copied();
+    let found = match room_entity {
+        Some(e) => q_b.get_mut(e).ok(),
+        None => None,
+    };
+    let (mut battle, room) = match found {
+        Some(found) => found,
+        None => {
+            // The room was culled.
+            position.pos_id = encounter.from;
+            commands.remove_resource::<Encounter>();
+            return;
+        }
+    };
+    // Actions sent after the one ending the encounter are dropped.
+    let action = match inputs.list.drain(..).next() {
+        Some(action) => action,
+        None => return,
+    };
+    recording.actions.push(RecordedAction {
+        tick: tick.0,
+        action,
+    });
+    let outcome = resolve_turn(action, &mut player, &mut battle);
+    log_turn(&mut encounter.log, action, &outcome, &battle.name);
+    if let Some(e) = room_entity {
+        commands.entity(e).insert(IsDirty);
+    }
+    match outcome.encounter_end(&player) {
+        EncounterEnd::Won => {
+            if battle.reward > 0 {
+                coins.amount += battle.reward;
+                commands.spawn().insert(TextFeedbackSpawn {
+                    text: format!("{} defeated\n+{} coins", battle.name, battle.reward),
+                    pos: room.position.into(),
+                });
+            }
+            // Changes the position again, so the room effects apply now it is won.
+            position.pos_id = encounter.room;
+        }
+        EncounterEnd::KnockedBack => {
+            position.pos_id = encounter.from;
+            for player in q_player.iter() {
+                commands.entity(player).insert(KnockBack);
+            }
+            // Fleeing stops the walk, otherwise it enters the room again for another exchange.
+            if action == CombatAction::Flee {
+                move_queue.rooms.clear();
+            }
+        }
+        EncounterEnd::Dead => {}
+    }
+    commands.remove_resource::<Encounter>();
+}
+
+fn log_turn(log: &mut Vec<String>, action: CombatAction, outcome: &TurnOutcome, enemy: &str) {
+    match action {
+        CombatAction::Attack => log.push(format!("You hit {} for {}", enemy, outcome.damage_dealt)),
+        CombatAction::Defend => log.push("You defend".to_string()),
+        CombatAction::UseItem if outcome.healed > 0f32 => {
+            log.push(format!("You drink a potion, +{} hp", outcome.healed))
+        }
+        CombatAction::UseItem => log.push("No potion to drink".to_string()),
+        CombatAction::Flee => {}
+    }
+    if outcome.damage_taken > 0f32 {
+        log.push(format!("{} hits you for {}", enemy, outcome.damage_taken));
+    }
+    if outcome.defeated {
+        log.push(format!("{} is defeated", enemy));
+    }
+}
+
+fn check_player_health(player: Res<PlayerStats>, mut state: ResMut<State<AppState>>) {
+    if player.is_changed() && player.is_dead() {
+        state.set(AppState::GameOver);
+    }
+}
+
 fn update_battle_room(
     mut commands: Commands,
     shapes: Res<ShapeMeshes>,
-    q_r: Query<(Entity, &Battle, &RoomEntity), With<IsDirty>>,
-    mut q_graphic: Query<&mut BattleGraphicRef>,
+    mut materials: ResMut<Assets<CircleGaugeMaterial>>,
+    q_r: Query<(Entity, &Battle, &RoomEntity, Option<&BattleGraphicRef>), With<IsDirty>>,
 ) {
-    for (e, b, r) in q_r.iter() {
-        let mut command_entity = commands.entity(e);
-        command_entity.remove::<IsDirty>();
-        if b.hp <= 0f32 {
-            dbg!("will remove");
-            match q_graphic.get_component_mut::<BattleGraphicRef>(dbg!(e)) {
-                Ok(graphic) => {
-                    dbg!("removing");
-                    commands
-                        .entity(graphic.entity)
-                        .remove_bundle::<MeshBundle>()
-                        .remove::<Handle<ColorMaterial>>();
-                    commands.entity(e).remove::<BattleGraphicRef>();
-                }
-                Err(e) => {
-                    dbg!(e);
+    for (e, b, r, graphic) in q_r.iter() {
+        commands.entity(e).remove::<IsDirty>();
+        // An existing gauge follows the enemy health, see `animate_health_gauges`.
+        if graphic.is_some() || b.hp <= 0f32 {
+            continue;
+        }
+        let ratio = health_ratio(b);
+        let material = materials.add(CircleGaugeMaterial {
+            color: Color::ORANGE_RED,
+            ratio,
+        });
+        let gauge = commands
+            .spawn_bundle(create_health_bundle(&shapes, r.position))
+            .insert(material)
+            .insert(HealthGauge {
+                battle: e,
+                displayed: ratio,
+            })
+            .id();
+        commands
+            .entity(e)
+            .insert(BattleGraphicRef { entity: gauge });
+    }
+}
+
+/// Moves each gauge towards the health of its enemy, and removes it once the enemy is
+/// defeated and the gauge emptied.
+fn animate_health_gauges(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut materials: ResMut<Assets<CircleGaugeMaterial>>,
+    q_battle: Query<&Battle>,
+    mut q_gauge: Query<(Entity, &mut HealthGauge, &Handle<CircleGaugeMaterial>)>,
+) {
+    for (e, mut gauge, material) in q_gauge.iter_mut() {
+        let battle = q_battle.get(gauge.battle);
+        let target = battle.as_ref().map_or(0f32, |b| health_ratio(b));
+        if gauge.displayed == target {
+            if target <= 0f32 {
+                commands.entity(e).despawn();
+                if battle.is_ok() {
+                    commands.entity(gauge.battle).remove::<BattleGraphicRef>();
                 }
             }
             continue;
         }
-        let graphic_entity = q_graphic
-            .get_component_mut::<BattleGraphicRef>(e)
-            .map_or_else(
-                |b| {
-                    dbg!(e);
-                    let graphic_entity = dbg!(commands.spawn().id());
-                    let graphic = BattleGraphicRef {
-                        entity: graphic_entity,
-                    };
-                    commands.entity(e).insert(graphic);
-                    graphic_entity
-                },
-                |b| dbg!(b.entity),
-            );
-        let bundle = create_health_bundle(&shapes, b, r.position);
-        commands.entity(graphic_entity).insert_bundle(bundle);
-        //        command_entity.insert(bundle.0).insert(bundle.1);
-    }
-}
-fn create_health_bundle(
-    shapes: &Res<ShapeMeshes>,
-    battle: &Battle,
-    position: (f32, f32),
-) -> MeshBundle {
-    let material = shapes.mat_orange.clone();
-
-    dbg!(format!(
-        "create battle info at {};{}",
-        position.0, position.1
-    ));
-    // TODO: prefer to put information in child: health points, flavour sprite...
+        let step = GAUGE_SPEED * time.delta_seconds();
+        gauge.displayed = if gauge.displayed > target {
+            (gauge.displayed - step).max(target)
+        } else {
+            (gauge.displayed + step).min(target)
+        };
+        if let Some(material) = materials.get_mut(material) {
+            material.ratio = gauge.displayed;
+        }
+    }
+}
+
+fn health_ratio(battle: &Battle) -> f32 {
+    if battle.max_hp <= 0f32 {
+        return 0f32;
+    }
+    (battle.hp / battle.max_hp).clamp(0f32, 1f32)
+}
+
+fn create_health_bundle(shapes: &ShapeMeshes, position: (f32, f32)) -> MeshBundle {
+    // Drawn around the room, above it.
     let mut transform = Transform::from_xyz(position.0, position.1, 16.0);
-    transform.scale = Vec3::ONE * 3.0;
-    let mesh = MeshBundle {
+    transform.scale = Vec3::ONE * 20.0;
+    MeshBundle {
         mesh: shapes.quad2x2.clone(),
         render_pipelines: RenderPipelines::from_pipelines(vec![RenderPipeline::new(
-            shapes.pipeline_circle.clone(),
+            shapes.pipeline_circle_gauge.clone(),
         )]),
         transform,
         ..Default::default()
-    };
-    mesh
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn enemy_hits_back_until_defeated() {
+        let mut player = PlayerStats {
+            defence: 1f32,
+            ..PlayerStats::default()
+        };
+        let mut battle = Battle {
+            hp: 2f32,
+            max_hp: 2f32,
+            attack: 3f32,
+            ..Battle::default()
+        };
+        let outcome = resolve_turn(CombatAction::Attack, &mut player, &mut battle);
+        assert_eq!(outcome.damage_dealt, 1f32);
+        assert_eq!(outcome.damage_taken, 2f32);
+        assert_eq!(player.hp, 8f32);
+        let outcome = resolve_turn(CombatAction::Attack, &mut player, &mut battle);
+        assert_eq!(battle.hp, 0f32);
+        assert!(outcome.defeated);
+        assert_eq!(outcome.damage_taken, 0f32);
+        assert_eq!(player.hp, 8f32);
+    }
+    #[test]
+    fn abilities_change_the_exchange() {
+        let mut player = PlayerStats::default();
+        let mut battle = Battle {
+            hp: 1f32,
+            ability: Some(EnemyAbility::FirstStrike),
+            ..Battle::default()
+        };
+        let outcome = resolve_turn(CombatAction::Attack, &mut player, &mut battle);
+        // Hit before being defeated.
+        assert!(outcome.defeated);
+        assert_eq!(player.hp, 9f32);
+        let mut battle = Battle {
+            hp: 2f32,
+            max_hp: 2f32,
+            ability: Some(EnemyAbility::Regeneration(0.5f32)),
+            ..Battle::default()
+        };
+        resolve_turn(CombatAction::Attack, &mut player, &mut battle);
+        assert_eq!(battle.hp, 1.5f32);
+        let mut battle = Battle {
+            hp: 2f32,
+            ability: Some(EnemyAbility::Armor(0.5f32)),
+            ..Battle::default()
+        };
+        assert_eq!(
+            resolve_turn(CombatAction::Attack, &mut player, &mut battle).damage_dealt,
+            0.5f32
+        );
+    }
+    #[test]
+    fn defending_and_potions() {
+        let mut player = PlayerStats {
+            hp: 5f32,
+            potions: 1,
+            ..PlayerStats::default()
+        };
+        let mut battle = Battle {
+            attack: 2f32,
+            ..Battle::default()
+        };
+        let outcome = resolve_turn(CombatAction::Defend, &mut player, &mut battle);
+        assert_eq!(outcome.damage_taken, 1f32);
+        let outcome = resolve_turn(CombatAction::UseItem, &mut player, &mut battle);
+        assert_eq!(outcome.healed, POTION_HEAL);
+        assert_eq!(player.hp, 6f32);
+        let outcome = resolve_turn(CombatAction::UseItem, &mut player, &mut battle);
+        assert_eq!(outcome.healed, 0f32);
+        assert_eq!(player.potions, 0);
+        let outcome = resolve_turn(CombatAction::Flee, &mut player, &mut battle);
+        assert_eq!(outcome, TurnOutcome::default());
+        assert_eq!(outcome.encounter_end(&player), EncounterEnd::KnockedBack);
+    }
+    #[test]
+    fn surviving_enemy_knocks_back() {
+        let mut player = PlayerStats::default();
+        let mut battle = Battle {
+            hp: 2f32,
+            max_hp: 2f32,
+            ..Battle::default()
+        };
+        let outcome = resolve_turn(CombatAction::Attack, &mut player, &mut battle);
+        assert_eq!(battle.hp, 1f32);
+        assert_eq!(outcome.encounter_end(&player), EncounterEnd::KnockedBack);
+        let outcome = resolve_turn(CombatAction::Attack, &mut player, &mut battle);
+        assert_eq!(outcome.encounter_end(&player), EncounterEnd::Won);
+        player.hp = 1f32;
+        let mut battle = Battle {
+            hp: 2f32,
+            attack: 2f32,
+            ..Battle::default()
+        };
+        let outcome = resolve_turn(CombatAction::Attack, &mut player, &mut battle);
+        assert_eq!(outcome.encounter_end(&player), EncounterEnd::Dead);
+    }
 }
@@ -1,12 +1,19 @@
+use std::collections::HashMap;
+
 use bevy::{prelude::*, render::pipeline::RenderPipeline};
 use bevy_prototype_lyon::{prelude::*, shapes::Circle};
+use map_gen::danger_forecast::{engulf_times, DangerGrowth, DangerZoneState};
 
 use crate::{
-    map_graph::{MapConfiguration, PlayerPositionDisplay},
+    map_graph::{MapConfiguration, MapDef, MapPosition, RoomId},
+    replay::SECONDS_PER_TICK,
     shapes::ShapeMeshes,
     AppState,
 };
 
+/// Rooms reached by the danger in less than this many seconds are shown by the overlay.
+const FORECAST_OVERLAY_HORIZON: f32 = 10f32;
+
 pub struct DangerZone {
     pub size: f32,
 }
@@ -20,6 +27,7 @@ pub struct DangerSpeedModifier {
 
 pub struct SpawnDangerZoneCommand {
     pub position: Vec2,
+    pub size: f32,
     pub radius_increase_per_second: f32,
 }
 
@@ -40,7 +48,7 @@ pub fn SpawnDangerZone(
         commands
             .spawn()
             .insert_bundle(mesh)
-            .insert(DangerZone { size: 1f32 })
+            .insert(DangerZone { size: s.size })
             .insert(GrowDangerZone {
                 radius_increase_per_second: s.radius_increase_per_second,
             });
@@ -48,40 +56,44 @@ pub fn SpawnDangerZone(
     }
 }
 
+/// Checks the room the player is in rather than its animated display, so replays die on
+/// the same tick.
 pub fn check_player_death(
     mut state: ResMut<State<AppState>>,
-    position: Query<&Transform, With<PlayerPositionDisplay>>,
+    map: Res<MapDef>,
+    position: Res<MapPosition>,
     dangers: Query<(&Transform, &DangerZone)>,
 ) {
-    for player_transform in position.iter() {
-        for (danger_transform, danger) in dangers.iter() {
-            let distance = danger_transform
-                .translation
-                .distance(player_transform.translation);
-            if distance < danger.size {
-                state.set(AppState::Menu);
-            }
+    let room = match map.room(position.pos_id) {
+        Ok(room) => room,
+        Err(_) => return,
+    };
+    let player_position: Vec2 = room.position.into();
+    for (danger_transform, danger) in dangers.iter() {
+        let distance = danger_transform
+            .translation
+            .truncate()
+            .distance(player_position);
+        if distance < danger.size {
+            state.set(AppState::GameOver);
         }
     }
 }
 
 pub fn danger_zone_grow_speedup(
-    time: Res<Time>,
     map_configuration: Res<MapConfiguration>,
     mut danger_speed_modifier: ResMut<DangerSpeedModifier>,
 ) {
-    danger_speed_modifier.multiplier += time.delta_seconds() * map_configuration.speed_gain_danger;
+    danger_speed_modifier.multiplier += SECONDS_PER_TICK * map_configuration.speed_gain_danger;
 }
 
 pub fn grow_danger_zone(
-    time: Res<Time>,
     danger_speed_modifier: Res<DangerSpeedModifier>,
     mut dangers: Query<(&GrowDangerZone, &mut DangerZone)>,
 ) {
     for (grow, mut d) in dangers.iter_mut() {
-        d.size += time.delta_seconds()
-            * grow.radius_increase_per_second
-            * danger_speed_modifier.multiplier;
+        d.size +=
+            SECONDS_PER_TICK * grow.radius_increase_per_second * danger_speed_modifier.multiplier;
     }
 }
 
@@ -90,3 +102,101 @@ pub fn update_danger_visual(mut q: Query<(Entity, &mut Transform, &DangerZone)>)
         t.scale = Vec3::ONE * d.size;
     }
 }
+
+/// Seconds until a danger zone reaches each known room, counted from the last refresh.
+/// Rooms the danger never reaches are missing.
+#[derive(Default)]
+pub struct DangerForecast {
+    pub engulf_times: HashMap<RoomId, f32>,
+}
+
+impl DangerForecast {
+    pub fn time_left(&self, id: RoomId) -> Option<f32> {
+        self.engulf_times.get(&id).copied()
+    }
+}
+
+/// When enabled, rooms soon reached by the danger are circled, redder as time runs out.
+#[derive(Default)]
+pub struct ForecastOverlay {
+    pub enabled: bool,
+}
+
+pub struct ForecastOverlayShape;
+
+/// Current state of a danger zone entity, for the computations of `map_gen`.
+pub fn danger_zone_state(
+    transform: &Transform,
+    zone: &DangerZone,
+    grow: &GrowDangerZone,
+) -> DangerZoneState {
+    DangerZoneState {
+        center: (transform.translation.x, transform.translation.y),
+        radius: zone.size,
+        radius_increase_per_second: grow.radius_increase_per_second,
+    }
+}
+
+pub fn update_danger_forecast(
+    time: Res<Time>,
+    mut timer: Local<f32>,
+    map: Res<MapDef>,
+    map_configuration: Res<MapConfiguration>,
+    danger_speed_modifier: Res<DangerSpeedModifier>,
+    dangers: Query<(&Transform, &DangerZone, &GrowDangerZone)>,
+    mut forecast: ResMut<DangerForecast>,
+) {
+    *timer += time.delta_seconds();
+    if *timer < 0.25f32 {
+        return;
+    }
+    *timer = 0f32;
+    let zones: Vec<DangerZoneState> = dangers
+        .iter()
+        .map(|(t, d, g)| danger_zone_state(t, d, g))
+        .collect();
+    // Mirrors `danger_zone_grow_speedup` and `grow_danger_zone`.
+    let growth = DangerGrowth {
+        multiplier: danger_speed_modifier.multiplier,
+        multiplier_gain_per_second: map_configuration.speed_gain_danger,
+    };
+    forecast.engulf_times = engulf_times(&map, &zones, &growth);
+}
+
+pub fn draw_forecast_overlay(
+    mut commands: Commands,
+    overlay: Res<ForecastOverlay>,
+    forecast: Res<DangerForecast>,
+    map: Res<MapDef>,
+    q_shapes: Query<Entity, With<ForecastOverlayShape>>,
+) {
+    if !overlay.is_changed() && !forecast.is_changed() {
+        return;
+    }
+    for e in q_shapes.iter() {
+        commands.entity(e).despawn();
+    }
+    if !overlay.enabled {
+        return;
+    }
+    for (id, time_left) in forecast.engulf_times.iter() {
+        if *time_left >= FORECAST_OVERLAY_HORIZON {
+            continue;
+        }
+        let room = match map.room(*id) {
+            Ok(room) => room,
+            Err(_) => continue,
+        };
+        let urgency = 1f32 - time_left / FORECAST_OVERLAY_HORIZON;
+        let ring = GeometryBuilder::build_as(
+            &Circle {
+                radius: 20f32,
+                center: Vec2::ZERO,
+            },
+            ShapeColors::new(Color::rgb(1f32, 1f32 - urgency, 0f32)),
+            DrawMode::Stroke(StrokeOptions::default().with_line_width(1f32 + 2f32 * urgency)),
+            Transform::from_xyz(room.position.0, room.position.1, 12.0),
+        );
+        commands.spawn_bundle(ring).insert(ForecastOverlayShape);
+    }
+}
@@ -0,0 +1,46 @@
+use bevy::prelude::*;
+use map_gen::export::{to_dot, to_svg, ExportState};
+
+use crate::{
+    danger::{danger_zone_state, DangerZone, GrowDangerZone},
+    map_graph::{MapDef, MapPosition},
+    room_definitions::RoomDefinitions,
+    text_feedback::TextFeedbackSpawn,
+};
+
+pub const EXPORT_SVG_PATH: &str = "map.svg";
+pub const EXPORT_DOT_PATH: &str = "map.dot";
+
+/// Spawn an entity with this component to write the current map to [`EXPORT_SVG_PATH`]
+/// and [`EXPORT_DOT_PATH`].
+pub struct ExportMapCommand;
+
+pub fn export_map(
+    mut commands: Commands,
+    q_export: Query<Entity, With<ExportMapCommand>>,
+    map: Res<MapDef>,
+    position: Res<MapPosition>,
+    definitions: Res<RoomDefinitions>,
+    dangers: Query<(&Transform, &DangerZone, &GrowDangerZone)>,
+) {
+    for e in q_export.iter() {
+        commands.entity(e).despawn();
+        let state = ExportState {
+            player: Some(position.pos_id),
+            danger_zones: dangers
+                .iter()
+                .map(|(t, d, g)| danger_zone_state(t, d, g))
+                .collect(),
+        };
+        let result = std::fs::write(EXPORT_SVG_PATH, to_svg(&map, &definitions, &state))
+            .and_then(|_| std::fs::write(EXPORT_DOT_PATH, to_dot(&map, &definitions, &state)));
+        let text = match result {
+            Ok(()) => format!("Exported to\n{}", EXPORT_SVG_PATH),
+            Err(err) => format!("Export failed\n{}", err),
+        };
+        commands.spawn().insert(TextFeedbackSpawn {
+            text,
+            pos: Vec2::ZERO,
+        });
+    }
+}
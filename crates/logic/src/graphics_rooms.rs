@@ -52,12 +52,11 @@ fn create_room_bundle(
     room: &Room,
 ) -> RoomGraphUpdate {
     let material = if is_reachable.is_some() {
-        match room.room_type {
-            crate::map_graph::RoomType::Safe => shapes.mat_white.clone(),
-            crate::map_graph::RoomType::Danger => shapes.mat_orange.clone(),
-            crate::map_graph::RoomType::Coins => shapes.mat_green.clone(),
-            crate::map_graph::RoomType::Price(_) => shapes.mat_fuchsia.clone(),
-        }
+        shapes
+            .mat_rooms
+            .get(&room.room_type)
+            .cloned()
+            .unwrap_or_else(|| shapes.mat_white.clone())
     } else {
         shapes.mat_gray.clone()
     };
@@ -83,10 +82,11 @@ pub fn create_room(
     commands: &mut Commands,
     room: &Room,
     id: RoomId,
+    entity: Entity,
     is_reachable: bool,
 ) -> Entity {
     let mut map_graphics = RoomGraphic { is_reachable: true };
-    let mut spawning = commands.entity(room.entity);
+    let mut spawning = commands.entity(entity);
     spawning.insert(id);
     spawning.insert(is_reachable);
     let for_init = map_graphics.init(shapes, is_reachable, room);
@@ -1,16 +1,32 @@
 use bevy::{prelude::*, reflect::List};
 use bevy_egui::{egui, EguiContext, EguiPlugin};
 use bevy_prototype_lyon::plugin::ShapePlugin;
-use map_graph::{Coins, MapConfiguration, MapGraphPlugin, RandomDeterministic, RoomChanceWeights};
+use combat::{Battle, CombatAction, CombatInputs, Encounter, PlayerStats, POTION_HEAL};
+use danger::{DangerForecast, ForecastOverlay};
+use export::ExportMapCommand;
+use map_gen::{generator::GENERATOR_VERSION, run_code::RunCode};
+use map_graph::{
+    Biomes, Coins, Connections, GeneratorVersion, Layout, MapConfiguration, MapDef, MapGraphPlugin,
+    MapPosition, RandomDeterministic, RoomChanceWeights, RoomEntities, WeightsError,
+};
+use replay::{
+    load_replay_from_disk, save_replay_to_disk, ReplayPlayback, RunRecording, REPLAY_PATH,
+};
+use room_definitions::RoomDefinitions;
+use save::{load_from_disk, PendingLoad, SaveGameCommand, SAVE_PATH};
+use text_feedback::TextFeedbackSpawn;
 use wasm_bindgen::prelude::*;
 
 pub mod combat;
 pub mod danger;
 pub mod delayed_destroy;
+pub mod export;
 pub mod graphics_rooms;
 pub mod map_graph;
 pub mod math_utils;
-mod poisson;
+pub mod replay;
+pub mod room_definitions;
+pub mod save;
 pub mod shapes;
 pub mod text_feedback;
 
@@ -19,6 +35,7 @@ pub enum AppState {
     Menu,
     Loading,
     Game,
+    GameOver,
 }
 
 pub struct GamePlugin;
@@ -35,16 +52,35 @@ impl Plugin for GamePlugin {
             .add_plugin(MapGraphPlugin)
             .add_state(AppState::Menu)
             .add_system(ui_menu.system())
-            .add_system(game_menu.system());
+            .add_system(game_menu.system())
+            .add_system(game_over_menu.system())
+            .add_system(encounter_panel.system());
     }
 }
 
+/// To call after any change to the room weights, the biomes are derived from them.
+fn room_weights_changed(
+    chance_rooms: &RoomChanceWeights,
+    definitions: &RoomDefinitions,
+    biomes: &mut Biomes,
+) -> Result<(), WeightsError> {
+    *biomes = Biomes::new(definitions, chance_rooms)?;
+    Ok(())
+}
+
 fn ui_menu(
+    mut commands: Commands,
     mut state: ResMut<State<AppState>>,
     mut map_configuration: ResMut<MapConfiguration>,
     mut chance_rooms: ResMut<RoomChanceWeights>,
+    mut biomes: ResMut<Biomes>,
+    definitions: Res<RoomDefinitions>,
     mut random: ResMut<RandomDeterministic>,
+    mut generator_version: ResMut<GeneratorVersion>,
     egui_context: ResMut<EguiContext>,
+    recording: Option<Res<RunRecording>>,
+    mut menu_message: Local<Option<String>>,
+    mut run_code_text: Local<String>,
 ) {
     if state.current() != &AppState::Menu {
         return;
@@ -72,39 +108,176 @@ fn ui_menu(
             if seed != random.seed {
                 random.set_seed(seed);
             }
+            if generator_version.0 != GENERATOR_VERSION {
+                ui.label(format!(
+                    "Generator version {} (latest {})",
+                    generator_version.0, GENERATOR_VERSION
+                ));
+                if ui.button("Use latest generator").clicked() {
+                    generator_version.0 = GENERATOR_VERSION;
+                }
+            }
+            ui.collapsing("Run code", |ui| {
+                ui.text_edit_singleline(&mut *run_code_text);
+                ui.horizontal(|ui| {
+                    if ui.button("Export").clicked() {
+                        *run_code_text = RunCode::new(
+                            generator_version.0,
+                            random.seed,
+                            &map_configuration,
+                            &chance_rooms,
+                        )
+                        .encode();
+                        ui.output().copied_text = run_code_text.clone();
+                        *menu_message = Some("Run code copied".to_string());
+                    }
+                    if ui.button("Import").clicked() {
+                        let applied = RunCode::decode(&run_code_text).and_then(|code| {
+                            code.apply(&mut random, &mut map_configuration, &mut chance_rooms)?;
+                            room_weights_changed(&chance_rooms, &definitions, &mut biomes)?;
+                            Ok(code)
+                        });
+                        *menu_message = Some(match applied {
+                            Ok(code) => {
+                                generator_version.0 = code.generator_version;
+                                "Run code imported".to_string()
+                            }
+                            Err(err) => format!("Could not import: {}", err),
+                        });
+                    }
+                });
+            });
             ui.collapsing("Room chances", |ui| {
+                let chance_rooms = &mut *chance_rooms;
+                let mut weights = chance_rooms.weights.clone();
                 let mut changed_weights = false;
-                changed_weights = changed_weights
-                    || input_usize(
-                        ui,
-                        "weight Danger",
-                        chance_rooms.weights.get_mut(0).unwrap(),
-                    );
-                changed_weights = changed_weights
-                    || input_usize(ui, "weight Safe", chance_rooms.weights.get_mut(1).unwrap());
-                changed_weights = changed_weights
-                    || input_usize(ui, "weight Coins", chance_rooms.weights.get_mut(2).unwrap());
-                changed_weights = changed_weights
-                    || input_usize(ui, "weight Price", chance_rooms.weights.get_mut(3).unwrap());
+                for (weight, definition) in weights.iter_mut().zip(chance_rooms.definitions.iter())
+                {
+                    let label = format!("weight {}", definition.type_room.0);
+                    changed_weights |= input_usize(ui, &label, weight);
+                }
+                // Weights that can't generate rooms are not kept, so runs never record them.
                 if changed_weights {
-                    chance_rooms.update_weights();
+                    let changed = chance_rooms.set_weights(weights).and_then(|()| {
+                        room_weights_changed(chance_rooms, &definitions, &mut biomes)
+                    });
+                    if let Err(err) = changed {
+                        *menu_message = Some(format!("Could not change room weights: {}", err));
+                    }
+                }
+            });
+            ui.collapsing("Layout", |ui| {
+                let mut layout = map_configuration.layout;
+                ui.radio_value(&mut layout, Layout::RingExpansion, "Ring expansion");
+                ui.radio_value(&mut layout, Layout::HexLattice, "Hex lattice");
+                ui.radio_value(&mut layout, Layout::HubAndSpoke, "Hub and spoke");
+                ui.radio_value(
+                    &mut layout,
+                    Layout::BranchingCorridors,
+                    "Branching corridors",
+                );
+                if layout != map_configuration.layout {
+                    map_configuration.layout = layout;
+                }
+            });
+            ui.checkbox(&mut map_configuration.biomes, "Biomes");
+            ui.checkbox(&mut map_configuration.planar_links, "No crossing links");
+            ui.collapsing("Connections", |ui| {
+                let mut connections = map_configuration.connections;
+                ui.radio_value(&mut connections, Connections::ClosestRoom, "Closest room");
+                let k = match connections {
+                    Connections::KNearest(k) => k,
+                    _ => 2,
+                };
+                ui.radio_value(&mut connections, Connections::KNearest(k), "K nearest");
+                ui.radio_value(
+                    &mut connections,
+                    Connections::RelativeNeighbourhood,
+                    "Relative neighbourhood",
+                );
+                ui.radio_value(&mut connections, Connections::Gabriel, "Gabriel");
+                if let Connections::KNearest(k) = &mut connections {
+                    input_usize(ui, "K", k);
+                }
+                if connections != map_configuration.connections {
+                    map_configuration.connections = connections;
+                }
+                let mut loop_density = map_configuration.loop_density;
+                if input_float(ui, "Loop density (0 to 1)", &mut loop_density) {
+                    map_configuration.loop_density = loop_density.clamp(0f32, 1f32);
+                }
+            });
+            ui.collapsing("Culling", |ui| {
+                let culling = &mut map_configuration.culling;
+                let mut graph_distance = culling.max_graph_distance.unwrap_or(0) as usize;
+                if input_usize(ui, "Max graph distance (0: off)", &mut graph_distance) {
+                    culling.max_graph_distance = Some(graph_distance as u32).filter(|d| *d > 0);
+                }
+                let mut world_distance = culling.max_world_distance.unwrap_or(0f32);
+                if input_float(ui, "Max world distance (0: off)", &mut world_distance) {
+                    culling.max_world_distance = Some(world_distance).filter(|d| *d > 0f32);
                 }
             });
 
-            /*
-            pub struct RoomChanceWeights {
-                pub weighted_index: WeightedIndex<usize>,
-                pub definitions: [RoomDefinition; 4],
-                pub rooms_to_create_on_move: u32,
-            }
-            pub struct RoomDefinition {
-                pub type_room: RoomType,
-                pub battle_chance: f64,
-                pub max_rooms_create: u32,
-            }*/
             if ui.button("Start").clicked() {
+                commands.remove_resource::<ReplayPlayback>();
                 state.set(AppState::Loading);
             }
+            if ui.button("Load").clicked() {
+                // The rooms created after loading use the tuning values of the saved run.
+                let applied = load_from_disk(SAVE_PATH).and_then(|save| {
+                    save.recording.apply_configuration(
+                        &definitions,
+                        &mut generator_version,
+                        &mut random,
+                        &mut map_configuration,
+                        &mut chance_rooms,
+                    )?;
+                    room_weights_changed(&chance_rooms, &definitions, &mut biomes)?;
+                    Ok(save)
+                });
+                match applied {
+                    Ok(save) => {
+                        *menu_message = None;
+                        commands.remove_resource::<ReplayPlayback>();
+                        commands.insert_resource(PendingLoad(save));
+                        state.set(AppState::Loading);
+                    }
+                    Err(err) => *menu_message = Some(format!("Could not load: {}", err)),
+                }
+            }
+            if ui.button("Replay").clicked() {
+                let applied = load_replay_from_disk(REPLAY_PATH).and_then(|replay| {
+                    replay.apply_configuration(
+                        &definitions,
+                        &mut generator_version,
+                        &mut random,
+                        &mut map_configuration,
+                        &mut chance_rooms,
+                    )?;
+                    room_weights_changed(&chance_rooms, &definitions, &mut biomes)?;
+                    Ok(replay)
+                });
+                match applied {
+                    Ok(replay) => {
+                        *menu_message = None;
+                        commands.insert_resource(ReplayPlayback::new(&replay));
+                        state.set(AppState::Loading);
+                    }
+                    Err(err) => *menu_message = Some(format!("Could not replay: {}", err)),
+                }
+            }
+            if let Some(recording) = &recording {
+                if ui.button("Save last replay").clicked() {
+                    *menu_message = Some(match save_replay_to_disk(REPLAY_PATH, recording) {
+                        Ok(()) => "Replay saved".to_string(),
+                        Err(err) => format!("Could not save replay: {}", err),
+                    });
+                }
+            }
+            if let Some(message) = &*menu_message {
+                ui.label(message);
+            }
         });
 }
 fn input_usize(ui: &mut egui::Ui, label: &str, value: &mut usize) -> bool {
@@ -141,8 +314,18 @@ fn input_float(ui: &mut egui::Ui, label: &str, value: &mut f32) -> bool {
     false
 }
 fn game_menu(
+    mut commands: Commands,
     mut state: ResMut<State<AppState>>,
     coins: Res<Coins>,
+    player: Res<PlayerStats>,
+    map_configuration: Res<MapConfiguration>,
+    biomes: Res<Biomes>,
+    map: Option<Res<MapDef>>,
+    position: Option<Res<MapPosition>>,
+    forecast: Res<DangerForecast>,
+    mut overlay: ResMut<ForecastOverlay>,
+    recording: Option<Res<RunRecording>>,
+    playback: Option<Res<ReplayPlayback>>,
     egui_context: ResMut<EguiContext>,
 ) {
     if state.current() != &AppState::Game {
@@ -152,7 +335,134 @@ fn game_menu(
         .default_width(200.0)
         .show(egui_context.ctx(), |ui| {
             ui.label("In game");
+            ui.label(format!("Health: {}/{}", player.hp.max(0f32), player.max_hp));
+            ui.label(format!("Coins: {}", coins.amount));
+            if let (true, Some(map), Some(position)) = (map_configuration.biomes, &map, &position) {
+                if let Ok(room) = map.room(position.pos_id) {
+                    ui.label(format!("Biome: {}", biomes.biome_at(&room.position).name));
+                }
+            }
+            if let Some(time_left) = position.as_ref().and_then(|p| forecast.time_left(p.pos_id)) {
+                ui.label(format!("Danger here in {:.1}s", time_left));
+            }
+            // Only written when toggled, the overlay is redrawn on change.
+            let mut show_forecast = overlay.enabled;
+            if ui.checkbox(&mut show_forecast, "Danger forecast").changed() {
+                overlay.enabled = show_forecast;
+            }
+            if let Some(playback) = &playback {
+                ui.label(if playback.is_finished() {
+                    "Replay finished"
+                } else {
+                    "Replaying..."
+                });
+            }
+            if ui.button("Save").clicked() {
+                commands.spawn().insert(SaveGameCommand);
+            }
+            if ui.button("Export map").clicked() {
+                commands.spawn().insert(ExportMapCommand);
+            }
+            if let Some(recording) = &recording {
+                if ui.button("Save replay").clicked() {
+                    if let Err(err) = save_replay_to_disk(REPLAY_PATH, recording) {
+                        commands.spawn().insert(TextFeedbackSpawn {
+                            text: format!("Could not save replay\n{}", err),
+                            pos: Vec2::ZERO,
+                        });
+                    }
+                }
+            }
+            if ui.button("Back").clicked() {
+                state.set(AppState::Menu);
+            }
+        });
+}
+
+/// Number of combat log lines shown.
+const ENCOUNTER_LOG_LINES: usize = 8;
+
+fn encounter_panel(
+    state: Res<State<AppState>>,
+    encounter: Option<Res<Encounter>>,
+    player: Res<PlayerStats>,
+    room_entities: Option<Res<RoomEntities>>,
+    battles: Query<&Battle>,
+    playback: Option<Res<ReplayPlayback>>,
+    mut combat_inputs: ResMut<CombatInputs>,
+    egui_context: ResMut<EguiContext>,
+) {
+    if state.current() != &AppState::Game {
+        return;
+    }
+    let (encounter, room_entities) = match (encounter, room_entities) {
+        (Some(encounter), Some(room_entities)) => (encounter, room_entities),
+        _ => return,
+    };
+    let battle = match room_entities
+        .entities
+        .get(&encounter.room)
+        .and_then(|e| battles.get(*e).ok())
+    {
+        Some(battle) => battle,
+        None => return,
+    };
+    egui::Window::new("Battle")
+        .collapsible(false)
+        .resizable(false)
+        .show(egui_context.ctx(), |ui| {
+            ui.label(format!(
+                "{}: {}/{} hp",
+                battle.name,
+                battle.hp.max(0f32),
+                battle.max_hp
+            ));
+            ui.label(format!("You: {}/{} hp", player.hp.max(0f32), player.max_hp));
+            ui.separator();
+            let start = encounter.log.len().saturating_sub(ENCOUNTER_LOG_LINES);
+            for line in encounter.log[start..].iter() {
+                ui.label(line.as_str());
+            }
+            ui.separator();
+            // Replays play the recorded actions.
+            if playback.is_some() {
+                return;
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Attack").clicked() {
+                    combat_inputs.list.push(CombatAction::Attack);
+                }
+                if ui.button("Defend").clicked() {
+                    combat_inputs.list.push(CombatAction::Defend);
+                }
+                let potion = format!("Potion +{} ({})", POTION_HEAL, player.potions);
+                if ui.button(potion).clicked() {
+                    combat_inputs.list.push(CombatAction::UseItem);
+                }
+                if ui.button("Flee").clicked() {
+                    combat_inputs.list.push(CombatAction::Flee);
+                }
+            });
+        });
+}
+
+fn game_over_menu(
+    mut state: ResMut<State<AppState>>,
+    coins: Res<Coins>,
+    recording: Option<Res<RunRecording>>,
+    egui_context: ResMut<EguiContext>,
+) {
+    if state.current() != &AppState::GameOver {
+        return;
+    }
+    egui::SidePanel::left("panel_game_over")
+        .default_width(200.0)
+        .show(egui_context.ctx(), |ui| {
+            ui.label("Game over");
             ui.label(format!("Coins: {}", coins.amount));
+            if let Some(recording) = &recording {
+                ui.label(format!("Moves: {}", recording.moves.len()));
+            }
             if ui.button("Back").clicked() {
                 state.set(AppState::Menu);
             }
@@ -1,155 +1,69 @@
-use crate::combat::{Battle, CombatPlugin, IsDirty};
+use crate::combat::{
+    Battle, BattleGraphicRef, CombatPlugin, Encounter, IsDirty, KnockBack, PlayerStats,
+    KNOCK_BACK_SPEED,
+};
 use crate::danger::{
-    danger_zone_grow_speedup, DangerSpeedModifier, SpawnDangerZone, SpawnDangerZoneCommand,
+    danger_zone_grow_speedup, draw_forecast_overlay, update_danger_forecast, DangerForecast,
+    DangerSpeedModifier, ForecastOverlay, SpawnDangerZone, SpawnDangerZoneCommand,
 };
 use crate::delayed_destroy::destroy_after;
+use crate::export::export_map;
 use crate::graphics_rooms::{create_room, RoomGraphic};
+use crate::replay::{
+    advance_game_tick, feed_replay_inputs, GameTick, RecordedMove, ReplayPlayback, RunRecording,
+};
+use crate::room_definitions::{RoomDefinitions, RoomDefinitionsPlugin, RoomEffect};
+use crate::save::{restore_run, save_game, PendingLoad};
 use crate::shapes::{CircleGaugeMaterial, ShapeMeshes, ShapesPlugin};
 use crate::text_feedback::{show_text_feedback, spawn_text_feedback, TextFeedbackSpawn};
 use crate::AppState;
 use crate::{
     danger::{check_player_death, grow_danger_zone, update_danger_visual},
     math_utils,
-    poisson::Poisson,
 };
 use bevy::render::pipeline::RenderPipeline;
 use bevy::{prelude::*, render::camera::OrthographicProjection, utils::HashMap};
 use bevy_prototype_lyon::{prelude::*, shapes::Line};
-use rand::distributions::WeightedIndex;
-use rand::prelude::Distribution;
-use rand::{thread_rng, Rng, RngCore, SeedableRng};
-use rand_chacha::ChaCha20Rng;
+pub use map_gen::{
+    biome::{Biome, Biomes},
+    configuration::MapConfiguration,
+    connection::Connections,
+    generator::Layout,
+    map::{MapDef, Room, RoomId, RoomType},
+    random::{RandomDeterministic, RandomStream},
+    room_chances::{RoomChanceWeights, RoomDefinition, WeightsError},
+};
+use map_gen::{
+    culling::cull,
+    danger_forecast::{is_route_safe, safe_route},
+    enemies::pick_enemy,
+    generator::{RoomFactory, GENERATOR_VERSION},
+    pathfinding::{shortest_path, AvoidRoomTypes},
+};
+use std::collections::VecDeque;
 pub struct MapGraphPlugin;
 
-#[derive(PartialEq, Eq, Hash, Default, Clone, Copy)]
-pub struct RoomId(usize);
-
 pub struct DisplayRoomReachable;
 
-pub struct Room {
-    pub connections: Vec<RoomId>,
-    pub position: (f32, f32),
-    pub room_type: RoomType,
-    pub visited: bool,
-    pub entity: Entity,
-}
 pub struct RoomEntity {
     // TODO: this probably be the transform actually, and remove position from Room..
     pub position: (f32, f32),
 }
 
+/// Entities spawned for each room of the [`MapDef`], and for each link between two rooms.
 #[derive(Default)]
-pub struct MapDef {
-    pub rooms: HashMap<RoomId, Room>,
-}
-
-#[derive(Clone, PartialEq, Hash, Eq, Debug)]
-pub enum RoomType {
-    Danger,
-    Safe,
-    Coins,
-    Price(u32),
-}
-
-impl Default for RoomType {
-    fn default() -> Self {
-        Self::Safe
-    }
-}
-
-pub struct RoomChanceWeights {
-    pub weights: [usize; 4],
-    pub weighted_index: WeightedIndex<usize>,
-    pub definitions: [RoomDefinition; 4],
-    pub rooms_to_create_on_move: u32,
-}
-
-impl RoomChanceWeights {
-    pub fn update_weights(&mut self) {
-        self.weighted_index = WeightedIndex::new(&self.weights).unwrap();
-    }
-}
-
-pub struct RoomDefinition {
-    pub type_room: RoomType,
-    pub battle_chance: f64,
-    pub max_rooms_create: u32,
-}
-
-impl Default for RoomChanceWeights {
-    fn default() -> Self {
-        let weights = [30, 50, 40, 20];
-        Self {
-            rooms_to_create_on_move: 5,
-            weights,
-            weighted_index: WeightedIndex::new(&weights).unwrap(),
-            definitions: [
-                RoomDefinition {
-                    type_room: RoomType::Danger,
-                    battle_chance: 0.1f64,
-                    max_rooms_create: 1,
-                },
-                RoomDefinition {
-                    type_room: RoomType::Safe,
-                    battle_chance: 0.3f64,
-                    max_rooms_create: 2,
-                },
-                RoomDefinition {
-                    type_room: RoomType::Coins,
-                    battle_chance: 0.5f64,
-                    max_rooms_create: 1,
-                },
-                RoomDefinition {
-                    type_room: RoomType::Price(7),
-                    battle_chance: 0.2f64,
-                    max_rooms_create: 1,
-                },
-            ],
-        }
-    }
-}
-
-pub struct MapConfiguration {
-    pub start_with_danger_zone: bool,
-    pub speed_gain_danger: f32,
-    pub speed_init_danger: f32,
-
-    pub weight_room_danger: f32,
-    pub weight_room_shop: f32,
-    pub weight_room_normal: f32,
-}
-
-pub struct RandomDeterministic {
-    pub random: ChaCha20Rng,
-    pub seed: u64,
-}
-
-impl Default for RandomDeterministic {
-    fn default() -> Self {
-        let seed = thread_rng().gen::<u64>();
-        Self {
-            random: ChaCha20Rng::seed_from_u64(seed),
-            seed,
-        }
-    }
-}
-
-impl RandomDeterministic {
-    pub fn set_seed(&mut self, seed: u64) {
-        self.seed = seed;
-        self.random = ChaCha20Rng::seed_from_u64(seed);
-    }
+pub struct RoomEntities {
+    pub entities: HashMap<RoomId, Entity>,
+    pub links: HashMap<(RoomId, RoomId), Entity>,
 }
 
-impl Default for MapConfiguration {
-    fn default() -> Self {
-        Self {
-            start_with_danger_zone: true,
-            speed_gain_danger: 0.1f32,
-            speed_init_danger: 10f32,
-            weight_room_danger: Default::default(),
-            weight_room_shop: Default::default(),
-            weight_room_normal: Default::default(),
+impl RoomEntities {
+    /// Key of the link between `a` and `b` in [`RoomEntities::links`], whatever their order.
+    pub fn link_key(a: RoomId, b: RoomId) -> (RoomId, RoomId) {
+        if a.0 <= b.0 {
+            (a, b)
+        } else {
+            (b, a)
         }
     }
 }
@@ -166,6 +80,15 @@ pub struct PlayerPositionDisplay;
 
 pub struct MainCamera;
 
+/// Added to the cost of routes entering a room which costs coins or spawns danger.
+const ROUTE_AVOID_PENALTY: f32 = 200f32;
+
+/// Rooms left to walk through to reach a distant room clicked by the player, next one first.
+#[derive(Default)]
+pub struct MoveQueue {
+    pub rooms: VecDeque<RoomId>,
+}
+
 #[derive(Default)]
 pub struct UserInputs {
     pub list: Vec<UserInput>,
@@ -173,31 +96,42 @@ pub struct UserInputs {
 
 pub enum UserInput {
     Click(Vec2),
+    /// Move to a connected room, used to replay recorded runs.
+    MoveTo(RoomId),
+}
+
+/// Version of the map generator used by the run, older than [`GENERATOR_VERSION`] when
+/// reproducing a run recorded before generation changed.
+#[derive(Clone, Copy)]
+pub struct GeneratorVersion(pub u32);
+
+impl Default for GeneratorVersion {
+    fn default() -> Self {
+        GeneratorVersion(GENERATOR_VERSION)
+    }
 }
 
 pub struct Coins {
     pub amount: u32,
 }
 
+/// Delay between moves, counted in [`GameTick`] so replayed moves wait as long as the
+/// recorded ones.
 pub struct Cooldown {
-    last_action_time: f32,
+    last_action_tick: u64,
     base_cooldown: f32,
 }
 
 impl Cooldown {
-    pub fn is_ready(&self, time: &Time) -> bool {
-        self.last_action_time + self.base_cooldown <= time.seconds_since_startup() as f32
+    pub fn is_ready(&self, tick: &GameTick) -> bool {
+        self.base_cooldown <= tick.seconds_since(self.last_action_tick)
     }
 
-    pub fn get_ratio(&self, time: &Time) -> f32 {
-        if self.is_ready(time) {
+    pub fn get_ratio(&self, tick: &GameTick) -> f32 {
+        if self.is_ready(tick) {
             return 1f32;
         }
-        let total_time = self.base_cooldown;
-        let time_left =
-            (self.last_action_time + self.base_cooldown) - time.seconds_since_startup() as f32;
-        let ratio = time_left / total_time;
-        1f32 - ratio
+        tick.seconds_since(self.last_action_tick) / self.base_cooldown
     }
 }
 
@@ -207,6 +141,7 @@ impl Plugin for MapGraphPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.add_plugin(ShapesPlugin);
         app.add_plugin(CombatPlugin);
+        app.add_plugin(RoomDefinitionsPlugin);
         let loading_startup_system_set =
             SystemSet::on_enter(AppState::Loading).with_system(create_map.system());
         app.add_system_set(loading_startup_system_set);
@@ -225,28 +160,66 @@ impl Plugin for MapGraphPlugin {
         let game_update_system_set = SystemSet::on_update(AppState::Game)
             .with_system(update_player_position.system())
             .with_system(base_input.system().label("base_input"))
-            .with_system(handle_input.system().after("base_input"))
+            .with_system(feed_replay_inputs.system().label("replay_input"))
+            .with_system(follow_move_queue.system().label("move_queue"))
+            .with_system(
+                handle_input
+                    .system()
+                    .label("handle_input")
+                    .after("base_input")
+                    .after("replay_input")
+                    .after("move_queue"),
+            )
+            .with_system(
+                advance_game_tick
+                    .system()
+                    .after("handle_input")
+                    .after("combat_input"),
+            )
             .with_system(create_new_rooms.system())
+            .with_system(cull_far_rooms.system())
             .with_system(SpawnDangerZone.system())
-            .with_system(check_player_death.system())
-            .with_system(grow_danger_zone.system())
+            // Ordered so replays grow the danger exactly as the recorded run.
+            .with_system(check_player_death.system().after("danger_growth"))
+            .with_system(
+                grow_danger_zone
+                    .system()
+                    .label("danger_growth")
+                    .after("danger_speedup"),
+            )
             .with_system(update_danger_visual.system())
             .with_system(update_map_reachabiliy.system())
-            .with_system(react_to_move_player.system())
+            .with_system(react_to_move_player.system().label("react_to_move"))
             .with_system(update_camera_position.system())
-            .with_system(danger_zone_grow_speedup.system())
+            .with_system(
+                danger_zone_grow_speedup
+                    .system()
+                    .label("danger_speedup")
+                    .after("react_to_move"),
+            )
+            .with_system(update_danger_forecast.system())
+            .with_system(draw_forecast_overlay.system())
             .with_system(spawn_text_feedback.system())
             .with_system(destroy_after.system())
             .with_system(cooldown_material_update.system())
+            .with_system(save_game.system())
+            .with_system(export_map.system())
             .with_system(show_text_feedback.system());
         app.add_system_set(game_update_system_set);
+        #[cfg(debug_assertions)]
+        app.add_system_set(SystemSet::on_update(AppState::Game).with_system(validate_map.system()));
 
         app.insert_resource(UserInputs::default());
+        app.insert_resource(MoveQueue::default());
+        app.insert_resource(DangerForecast::default());
+        app.insert_resource(ForecastOverlay::default());
         app.insert_resource(DangerSpeedModifier { multiplier: 1f32 });
         app.insert_resource(Coins { amount: 0u32 });
         app.insert_resource(MapConfiguration::default());
         app.insert_resource(RandomDeterministic::default());
+        app.insert_resource(GeneratorVersion::default());
         app.insert_resource(RoomChanceWeights::default());
+        app.insert_resource(Biomes::default());
     }
 }
 
@@ -286,116 +259,128 @@ fn loading_update(mut state: ResMut<State<AppState>>) {
 
 fn create_map(
     mut commands: Commands,
-    time: Res<Time>,
     map_configuration: Res<MapConfiguration>,
+    room_chance: Res<RoomChanceWeights>,
+    definitions: Res<RoomDefinitions>,
     mut random: ResMut<RandomDeterministic>,
+    mut generator_version: ResMut<GeneratorVersion>,
+    pending_load: Option<Res<PendingLoad>>,
+    mut state: ResMut<State<AppState>>,
 ) {
-    let seed = random.seed;
-    random.set_seed(seed);
-
     let mut cameraBundle = OrthographicCameraBundle::new_2d();
     cameraBundle.orthographic_projection.scale = 0.3;
     commands.spawn_bundle(cameraBundle).insert(MainCamera);
     commands.insert_resource(Coins { amount: 0u32 });
+    commands.insert_resource(PlayerStats::default());
     commands.insert_resource(DangerSpeedModifier { multiplier: 1f32 });
+    commands.insert_resource(MoveQueue::default());
+    commands.remove_resource::<Encounter>();
+    commands.spawn().insert(Cooldown {
+        last_action_tick: 0,
+        base_cooldown: 0.5f32,
+    });
 
-    let mut positions = vec![(0f32, 0f32)];
-    let poisson = Poisson::new();
-    let mut root_index = RoomId(0);
-    let nb_new_shapes = 2;
-    let mut new_map = HashMap::default();
-    for i in 0..positions.len() {
-        new_map.insert(
-            RoomId(i),
-            Room {
-                connections: Default::default(),
-                position: positions[i],
-                room_type: RoomType::Safe,
-                entity: commands
-                    .spawn()
-                    .insert(RoomEntity {
-                        position: positions[i],
-                    })
-                    .id(),
-                visited: false,
-            },
+    if let Some(pending_load) = pending_load {
+        restore_run(
+            &mut commands,
+            &pending_load.0,
+            &mut random,
+            &mut generator_version,
         );
+        commands.remove_resource::<PendingLoad>();
+        return;
     }
-    let mut new_map = MapDef { rooms: new_map };
 
-    let mut rng = &mut random.random;
-    let mut room_id_to_create = RoomId(1);
-    while root_index.0 < positions.len() && new_map.rooms.len() < nb_new_shapes {
-        let ref_point = positions[root_index.0];
+    let seed = random.seed;
+    random.set_seed(seed);
 
-        if let Some(new_position) =
-            poisson.compute_new_position(&positions, &ref_point, 40f32, 5, &mut rng)
-        {
-            {
-                match new_map.rooms.entry(root_index) {
-                    std::collections::hash_map::Entry::Occupied(mut room) => {
-                        room.get_mut().connections.push(room_id_to_create);
-                    }
-                    std::collections::hash_map::Entry::Vacant(_) => return,
-                };
-            }
-            let new_room = Room {
-                connections: vec![root_index],
-                position: new_position,
-                room_type: RoomType::Safe,
-                entity: commands
-                    .spawn()
-                    .insert(RoomEntity {
-                        position: new_position,
-                    })
-                    .id(),
-                visited: false,
-            };
-            {
-                new_map.rooms.insert(room_id_to_create, new_room);
+    let factory =
+        match RoomFactory::new(&mut random, &room_chance).with_version(generator_version.0) {
+            Ok(factory) => factory,
+            Err(err) => {
+                error!("Could not create the map: {}", err);
+                state.set(AppState::Menu);
+                return;
             }
-            positions.push(new_position);
-            room_id_to_create.0 += 1;
-        } else {
-            root_index.0 += 1;
-        }
-    }
+        };
+    let new_map = map_configuration.layout.generator(factory).create_map();
+    let room_entities = spawn_room_entities(&mut commands, &new_map);
     commands.insert_resource(new_map);
+    commands.insert_resource(room_entities);
     commands.insert_resource(MapPosition {
         pos_id: RoomId(0),
         will_move: None,
     });
+    commands.insert_resource(GameTick(0));
+    commands.insert_resource(RunRecording::new(
+        generator_version.0,
+        seed,
+        &map_configuration,
+        &definitions,
+        &room_chance,
+    ));
     // Spawn a first danger zone
     if map_configuration.start_with_danger_zone {
         commands.spawn().insert(SpawnDangerZoneCommand {
             position: [20f32, 20f32].into(),
+            size: 1f32,
             radius_increase_per_second: map_configuration.speed_init_danger,
         });
     }
-    commands.spawn().insert(Cooldown {
-        last_action_time: time.seconds_since_startup() as f32,
-        base_cooldown: 0.5f32,
-    });
 }
 
-fn init_display_map(mut commands: Commands, shapes: Res<ShapeMeshes>, map: Res<MapDef>) {
-    if map.rooms.is_empty() {
-        return;
+pub fn spawn_room_entities(commands: &mut Commands, map: &MapDef) -> RoomEntities {
+    let mut room_entities = RoomEntities::default();
+    for (id, room) in map.rooms() {
+        let entity = commands
+            .spawn()
+            .insert(RoomEntity {
+                position: room.position,
+            })
+            .id();
+        room_entities.entities.insert(*id, entity);
     }
-    let mut visit_queue = vec![&RoomId(0)];
-    let mut visit_index = RoomId(0);
-    while visit_index.0 < visit_queue.len() {
-        let room = &map.rooms[visit_queue[visit_index.0]];
-        create_room(&shapes, &mut commands, room, visit_index, true);
-        for connection in &room.connections {
-            create_link(&mut commands, &map.rooms[connection], room);
-            if !visit_queue.contains(&connection) {
-                visit_queue.push(connection);
+    room_entities
+}
+
+fn init_display_map(
+    mut commands: Commands,
+    shapes: Res<ShapeMeshes>,
+    map_configuration: Res<MapConfiguration>,
+    biomes: Res<Biomes>,
+    map: Res<MapDef>,
+    position: Res<MapPosition>,
+    mut room_entities: ResMut<RoomEntities>,
+) {
+    // Start from the player: earlier rooms may have been culled.
+    let first_room = match map.room(position.pos_id) {
+        Ok(room) => room,
+        Err(_) => return,
+    };
+    let mut visit_queue = vec![position.pos_id];
+    let mut visit_index = 0;
+    while visit_index < visit_queue.len() {
+        let room_id = visit_queue[visit_index];
+        visit_index += 1;
+        let (room, entity) = match (map.room(room_id), room_entities.entities.get(&room_id)) {
+            (Ok(room), Some(entity)) => (room, *entity),
+            _ => continue,
+        };
+        create_room(&shapes, &mut commands, room, room_id, entity, true);
+        for connection in room.connections() {
+            let key = RoomEntities::link_key(room_id, *connection);
+            if !room_entities.links.contains_key(&key) {
+                if let Ok(other) = map.room(*connection) {
+                    let color = link_color(&map_configuration, &biomes, room);
+                    let link = create_link(&mut commands, other, room, color);
+                    room_entities.links.insert(key, link);
+                }
+            }
+            if !visit_queue.contains(connection) {
+                visit_queue.push(*connection);
             }
         }
-        visit_index.0 += 1;
     }
-    let first_room = &map.rooms[&RoomId(0)];
     let mut charac_transform =
         Transform::from_xyz(first_room.position.0, first_room.position.1, 0.0);
     charac_transform.scale = Vec3::ONE * 22.0;
@@ -418,8 +403,12 @@ fn base_input(
     window: Res<Windows>,
     mut user_inputs: ResMut<UserInputs>,
     mouse_button_input: Res<Input<MouseButton>>,
+    playback: Option<Res<ReplayPlayback>>,
     q_camera: Query<(&Transform, &OrthographicProjection), With<MainCamera>>,
 ) {
+    if playback.is_some() {
+        return;
+    }
     if mouse_button_input.just_pressed(MouseButton::Left) {
         let win = window.get_primary().expect("no primary window");
         if let Some(pos) = win.cursor_position() {
@@ -454,10 +443,16 @@ fn update_map_reachabiliy(
         return;
     }
     *timer = 0f32;
-    let current_room = &map.rooms[&player_pos.pos_id];
+    let current_room = match map.room(player_pos.pos_id) {
+        Ok(room) => room,
+        Err(_) => return,
+    };
     for (e, r, mut g) in rooms.iter_mut() {
-        let room_to_update = &map.rooms[r];
-        let is_reachable = current_room.connections.contains(r);
+        let room_to_update = match map.room(*r) {
+            Ok(room) => room,
+            Err(_) => continue,
+        };
+        let is_reachable = current_room.connections().contains(r);
         if let Some(update_components) = g.updateReachability(&shapes, is_reachable, room_to_update)
         {
             commands
@@ -469,24 +464,17 @@ fn update_map_reachabiliy(
     }
 }
 
-fn react_to_will_move(mut position_changed: ResMut<MapPosition>) {
-    if !position_changed.is_changed() {
-        return;
-    }
-    if position_changed.will_move.is_none() {
-        return;
-    }
-    position_changed.pos_id = position_changed.will_move.unwrap();
-    position_changed.will_move = None;
-}
-
 fn react_to_move_player(
     mut commands: Commands,
     mut coins: ResMut<Coins>,
     mut map: ResMut<MapDef>,
     mut danger_zone_grow_speedup: ResMut<DangerSpeedModifier>,
     map_configuration: Res<MapConfiguration>,
+    biomes: Res<Biomes>,
+    definitions: Res<RoomDefinitions>,
     position_changed: Res<MapPosition>,
+    room_entities: Res<RoomEntities>,
+    q_battle: Query<&Battle>,
 ) {
     if !position_changed.is_changed() {
         return;
@@ -494,18 +482,25 @@ fn react_to_move_player(
     if position_changed.will_move.is_some() {
         return;
     }
+    // The effects of a room apply once its enemy is defeated.
+    let guarded = room_entities
+        .entities
+        .get(&position_changed.pos_id)
+        .and_then(|e| q_battle.get(*e).ok())
+        .map_or(false, |b| b.hp > 0f32);
+    if guarded {
+        return;
+    }
 
-    if let Some(r) = map.rooms.get(&position_changed.pos_id) {
+    if let Ok(r) = map.room(position_changed.pos_id) {
         let current_position = [r.position.0, r.position.1].into();
         let direction_for_danger: Vec2 = {
             let mut direction = Vec2::ZERO;
-            for i in 0..r.connections.len() {
-                let mut direction_to_room: Vec2 = map
-                    .rooms
-                    .get(&r.connections[i])
-                    .expect("map is incorrect")
-                    .position
-                    .into();
+            for connection in r.connections() {
+                let mut direction_to_room: Vec2 = match map.room(*connection) {
+                    Ok(room) => room.position.into(),
+                    Err(_) => continue,
+                };
                 direction_to_room -= current_position;
                 direction_to_room = direction_to_room.normalize_or_zero();
                 direction += direction_to_room;
@@ -513,26 +508,39 @@ fn react_to_move_player(
             direction = direction.normalize_or_zero();
             direction * 30f32
         };
-        match r.room_type {
-            RoomType::Safe => {}
-            RoomType::Danger => {
-                commands.spawn().insert(SpawnDangerZoneCommand {
-                    position: direction_for_danger + current_position,
-                    radius_increase_per_second: map_configuration.speed_init_danger,
-                });
-            }
-            RoomType::Coins => {
-                coins.amount += 1;
-            }
-            RoomType::Price(price) => {
-                coins.amount = (coins.amount - price).max(0);
-                danger_zone_grow_speedup.multiplier *= 0.5f32;
+        let effects = definitions
+            .get(&r.room_type)
+            .map_or(&[][..], |d| &d.effects[..]);
+        for effect in effects {
+            match effect {
+                RoomEffect::AddCoins(amount) => {
+                    coins.amount += amount;
+                }
+                RoomEffect::PayCoins(amount) => {
+                    coins.amount = coins.amount.saturating_sub(*amount);
+                }
+                RoomEffect::SpawnDangerZone => {
+                    let biome_multiplier = if map_configuration.biomes {
+                        biomes.biome_at(&r.position).danger_speed_multiplier
+                    } else {
+                        1f32
+                    };
+                    commands.spawn().insert(SpawnDangerZoneCommand {
+                        position: direction_for_danger + current_position,
+                        size: 1f32,
+                        radius_increase_per_second: map_configuration.speed_init_danger
+                            * biome_multiplier,
+                    });
+                }
+                RoomEffect::ScaleDangerSpeed(factor) => {
+                    danger_zone_grow_speedup.multiplier *= factor;
+                }
             }
         }
-        if let Some(r) = map.rooms.get_mut(&position_changed.pos_id) {
-            if r.room_type != RoomType::Safe {
+        if let Ok(r) = map.room_mut(position_changed.pos_id) {
+            if r.room_type != RoomType::safe() {
                 // We visited this room so reset its type to Safe.
-                r.room_type = RoomType::Safe;
+                r.room_type = RoomType::safe();
             }
             if !r.visited {
                 r.visited = true;
@@ -544,7 +552,7 @@ fn react_to_move_player(
     }
 }
 fn cooldown_material_update(
-    time: Res<Time>,
+    tick: Res<GameTick>,
     shapes: ResMut<ShapeMeshes>,
     mut materials_circle_gauge: ResMut<Assets<CircleGaugeMaterial>>,
     q_cooldown: Query<(&Cooldown)>,
@@ -554,7 +562,7 @@ fn cooldown_material_update(
         None => return,
     };
     if let Some(mat) = materials_circle_gauge.get_mut(shapes.mat_circle_gauge.clone()) {
-        mat.ratio = cooldown.get_ratio(&time);
+        mat.ratio = cooldown.get_ratio(&tick);
         if mat.ratio >= 1.0f32 {
             mat.color = Color::WHITE
         } else {
@@ -566,212 +574,367 @@ fn cooldown_material_update(
 fn handle_input(
     mut commands: Commands,
     map: Res<MapDef>,
-    time: Res<Time>,
+    tick: Res<GameTick>,
     coins: Res<Coins>,
+    definitions: Res<RoomDefinitions>,
+    forecast: Res<DangerForecast>,
     mut inputs: ResMut<UserInputs>,
     mut position: ResMut<MapPosition>,
+    mut move_queue: ResMut<MoveQueue>,
+    mut recording: ResMut<RunRecording>,
+    encounter: Option<Res<Encounter>>,
     mut q_cooldown: Query<(&mut Cooldown)>,
 ) {
-    let current_room = map.rooms.get(&position.pos_id).unwrap();
+    if encounter.is_some() {
+        // Moves wait for the end of the battle, replayed ones included.
+        inputs
+            .list
+            .retain(|input| matches!(input, UserInput::MoveTo(_)));
+        return;
+    }
+    let current_room = match map.room(position.pos_id) {
+        Ok(room) => room,
+        Err(_) => return,
+    };
     let mut cooldown = match q_cooldown.iter_mut().last() {
         Some(c) => c,
         None => return,
     };
-    for click in inputs.list.iter() {
-        let UserInput::Click(click) = click;
-        if !cooldown.is_ready(&time) {
+    let to_handle: Vec<UserInput> = inputs.list.drain(..).collect();
+    for input in to_handle {
+        if let UserInput::Click(click) = &input {
+            let clicked = map.spatial().pick(&(click.x, click.y), 15.0);
+            if let Some(clicked) = clicked
+                .filter(|id| *id != position.pos_id && !current_room.connections().contains(id))
+            {
+                // Distant room: walk there one room at a time, see `follow_move_queue`.
+                move_queue.rooms = plan_route(
+                    &map,
+                    &definitions,
+                    &forecast,
+                    cooldown.base_cooldown,
+                    position.pos_id,
+                    clicked,
+                );
+                if move_queue.rooms.is_empty() {
+                    commands.spawn().insert(TextFeedbackSpawn {
+                        text: "No route".to_string(),
+                        pos: Vec2::ZERO,
+                    });
+                }
+                continue;
+            }
+            move_queue.rooms.clear();
+        }
+        if !cooldown.is_ready(&tick) {
+            if let UserInput::MoveTo(_) = input {
+                // Replayed moves wait for the cooldown, as the player had to.
+                inputs.list.push(input);
+                continue;
+            }
             commands.spawn().insert(TextFeedbackSpawn {
                 text: format!("Not Ready\n"),
                 pos: Vec2::new(0f32, 0f32),
             });
             break;
         }
-        for id in map.rooms.keys() {
-            if !current_room.connections.contains(id) {
-                continue;
+        let target = match &input {
+            UserInput::Click(click) => map
+                .spatial()
+                .nearest(&(click.x, click.y), 15.0, |id| {
+                    current_room.connections().contains(&id)
+                })
+                .map(|(id, _)| id),
+            UserInput::MoveTo(target) => {
+                Some(*target).filter(|id| current_room.connections().contains(id))
             }
-            let r = map.rooms.get(id).unwrap();
-
-            let room_position = Vec2::new(r.position.0, r.position.1);
-            let distance_to_room = room_position.distance(*click);
-            if distance_to_room < 15.0 {
-                match r.room_type {
-                    RoomType::Danger => {}
-                    RoomType::Safe => {}
-                    RoomType::Coins => {}
-                    RoomType::Price(price) => {
-                        if coins.amount < price {
-                            // TODO: spawn a feedback: not enough coins!
-                            commands.spawn().insert(TextFeedbackSpawn {
-                                text: format!("Not enough coins\n{}/{}", coins.amount, price),
-                                pos: r.position.into(),
-                            });
-                            continue;
-                        }
-                    }
-                };
-                position.will_move = Some(*id);
-                cooldown.last_action_time = time.seconds_since_startup() as f32;
-                break;
+        };
+        let id = match target {
+            Some(id) => id,
+            None => {
+                move_queue.rooms.clear();
+                continue;
             }
+        };
+        let r = match map.room(id) {
+            Ok(room) => room,
+            Err(_) => continue,
+        };
+        let price = definitions.get(&r.room_type).map_or(0, |d| d.price());
+        if coins.amount < price {
+            commands.spawn().insert(TextFeedbackSpawn {
+                text: format!("Not enough coins\n{}/{}", coins.amount, price),
+                pos: r.position.into(),
+            });
+            move_queue.rooms.clear();
+            continue;
+        }
+        position.will_move = Some(id);
+        cooldown.last_action_tick = tick.0;
+        recording.moves.push(RecordedMove {
+            tick: tick.0,
+            room: id,
+        });
+    }
+}
+
+/// Rooms to walk through from `from` to `to`, avoiding rooms with a cost when possible.
+///
+/// When that route would be caught by the danger, the route with the fewest moves staying
+/// ahead of it is preferred, assuming each move takes `hop_duration` seconds.
+/// Empty if there is no route.
+fn plan_route(
+    map: &MapDef,
+    definitions: &RoomDefinitions,
+    forecast: &DangerForecast,
+    hop_duration: f32,
+    from: RoomId,
+    to: RoomId,
+) -> VecDeque<RoomId> {
+    let avoid = AvoidRoomTypes {
+        room_types: definitions
+            .rooms
+            .iter()
+            .filter(|d| d.price() > 0 || d.effects.contains(&RoomEffect::SpawnDangerZone))
+            .map(|d| d.room_type())
+            .collect(),
+        penalty: ROUTE_AVOID_PENALTY,
+    };
+    let path = match shortest_path(map, from, to, &avoid) {
+        Ok(Some(path)) => path,
+        _ => return VecDeque::new(),
+    };
+    let rooms = if is_route_safe(&path.rooms, &forecast.engulf_times, hop_duration) {
+        path.rooms
+    } else {
+        match safe_route(map, from, to, &forecast.engulf_times, hop_duration) {
+            Ok(Some(safe)) => safe.rooms,
+            _ => path.rooms,
         }
+    };
+    rooms.into_iter().skip(1).collect()
+}
+
+/// Sends the next move of the [`MoveQueue`] once the cooldown is over. A battle blocking
+/// the way opens an [`Encounter`], the walk goes on once it is won.
+fn follow_move_queue(
+    tick: Res<GameTick>,
+    position: Res<MapPosition>,
+    mut move_queue: ResMut<MoveQueue>,
+    mut inputs: ResMut<UserInputs>,
+    encounter: Option<Res<Encounter>>,
+    q_cooldown: Query<&Cooldown>,
+) {
+    if encounter.is_some() {
+        return;
+    }
+    if move_queue.rooms.front() == Some(&position.pos_id) {
+        move_queue.rooms.pop_front();
+    }
+    let next = match move_queue.rooms.front() {
+        Some(next) => *next,
+        None => return,
+    };
+    if position.will_move.is_some()
+        || inputs
+            .list
+            .iter()
+            .any(|input| matches!(input, UserInput::MoveTo(_)))
+    {
+        return;
+    }
+    if q_cooldown
+        .iter()
+        .last()
+        .map_or(false, |c| c.is_ready(&tick))
+    {
+        inputs.list.push(UserInput::MoveTo(next));
     }
-    inputs.list.clear();
 }
 
 fn create_new_rooms(
     mut commands: Commands,
     shapes: Res<ShapeMeshes>,
-    room_chance: ResMut<RoomChanceWeights>,
+    map_configuration: Res<MapConfiguration>,
+    room_chance: Res<RoomChanceWeights>,
+    definitions: Res<RoomDefinitions>,
+    biomes: Res<Biomes>,
+    generator_version: Res<GeneratorVersion>,
     mut random: ResMut<RandomDeterministic>,
     mut map: ResMut<MapDef>,
+    mut room_entities: ResMut<RoomEntities>,
     q_create: Query<(Entity, &MapCreateRoom)>,
 ) {
-    let min_distance_between_rooms = 40f32;
-
+    let connections = map_configuration.connections.strategy();
     for (e, create) in q_create.iter() {
-        let mut rng = &mut random.random;
-        let poisson = Poisson::new();
-
-        let mut duplicates: std::collections::HashMap<&RoomType, u32> =
-            std::collections::HashMap::default();
-
-        for _ in 0..room_chance.rooms_to_create_on_move {
-            let existing_points: Vec<(f32, f32)> = map.rooms.values().map(|r| r.position).collect();
-            let room_id_to_create = RoomId(map.rooms.len());
-            if let Some(ref_point) = map.rooms.get(&create.from_room_id).map(|f| f.position) {
-                if let Some(new_position) = poisson.compute_new_position(
-                    &existing_points,
-                    &ref_point,
-                    min_distance_between_rooms,
-                    10,
-                    &mut rng,
-                ) {
-                    let type_index = room_chance.weighted_index.sample(&mut rng);
-                    let definition = &room_chance.definitions[type_index];
-                    //appearances.push(definition.type_room.clone());
-                    let counter = duplicates
-                        .entry(&definition.type_room)
-                        .or_insert(definition.max_rooms_create);
-                    if *counter == 0 {
-                        continue;
-                    }
-                    *counter -= 1;
-
-                    let battle = if rng.gen::<f64>() < definition.battle_chance {
-                        Some(Battle {
-                            hp: 1.0,
-                            attack: 1.0,
-                        })
-                    } else {
-                        None
-                    };
-                    let room_type = &definition.type_room;
-                    {
-                        match map.rooms.entry(create.from_room_id) {
-                            std::collections::hash_map::Entry::Occupied(mut room) => {
-                                room.get_mut().connections.push(room_id_to_create);
-                            }
-                            std::collections::hash_map::Entry::Vacant(_) => return,
-                        };
-                    }
-                    let mut new_room = Room {
-                        connections: vec![create.from_room_id],
-                        position: new_position,
-                        entity: commands
-                            .spawn()
-                            .insert(RoomEntity {
-                                position: new_position,
-                            })
-                            .id(),
-                        room_type: room_type.clone(),
-                        visited: false,
-                    };
-                    if let Some(battle) = &battle {
-                        commands
-                            .entity(new_room.entity)
-                            .insert(battle.clone())
-                            .insert(IsDirty);
-                    }
-                    create_room(&shapes, &mut commands, &new_room, room_id_to_create, true);
-                    create_link(
-                        &mut commands,
-                        map.rooms.get(&create.from_room_id).unwrap(),
-                        &new_room,
-                    );
-
-                    let origin_position = map.rooms.get(&create.from_room_id).unwrap().position;
-                    let mut existing_point_without_origin = existing_points.clone();
-                    existing_point_without_origin.retain(|r| r != &origin_position);
-                    let (closest_point, dist_sqrd) =
-                        find_closest(&existing_point_without_origin, &new_position);
-
-                    if dist_sqrd < (min_distance_between_rooms * 1.5f32).powi(2) {
-                        let r1 = map
-                            .rooms
-                            .iter_mut()
-                            .find(|r| r.1.position == (closest_point.x, closest_point.y))
-                            .map(|room_from| {
-                                room_from.1.connections.push(room_id_to_create);
-                                room_from.0.clone()
-                            });
-                        if let Some(r1) = r1 {
-                            new_room.connections.push(r1);
-                            create_link(&mut commands, map.rooms.get(&r1).unwrap(), &new_room);
-                        }
-                    }
-
-                    {
-                        map.rooms.insert(room_id_to_create, new_room);
-                    }
+        commands.entity(e).despawn();
+        let factory = RoomFactory::new(&mut random, &room_chance)
+            .with_planar_links(map_configuration.planar_links)
+            .with_connections(connections.as_ref(), map_configuration.loop_density)
+            .with_version(generator_version.0);
+        let mut factory = match factory {
+            Ok(factory) => factory,
+            Err(err) => {
+                error!("Could not create rooms: {}", err);
+                continue;
+            }
+        };
+        if map_configuration.biomes {
+            factory = factory.with_biomes(&biomes);
+        }
+        let created = match map_configuration
+            .layout
+            .generator(factory)
+            .expand(&mut map, create.from_room_id)
+        {
+            Ok(created) => created,
+            Err(err) => {
+                error!("Could not create rooms: {}", err);
+                continue;
+            }
+        };
+        for new_room in created {
+            let room = match map.room(new_room.id) {
+                Ok(room) => room,
+                Err(_) => continue,
+            };
+            let entity = commands
+                .spawn()
+                .insert(RoomEntity {
+                    position: room.position,
+                })
+                .id();
+            room_entities.entities.insert(new_room.id, entity);
+            if new_room.battle {
+                let level = definitions.difficulty.level(&map, new_room.id);
+                let battle = pick_enemy(
+                    &definitions.enemies,
+                    &definitions.difficulty,
+                    level,
+                    random.stream(RandomStream::Enemies),
+                )
+                .map_or_else(Battle::default, Battle::from);
+                commands.entity(entity).insert(battle).insert(IsDirty);
+            }
+            create_room(&shapes, &mut commands, room, new_room.id, entity, true);
+            for link in new_room.links.iter() {
+                if let Ok(other) = map.room(*link) {
+                    let color = link_color(&map_configuration, &biomes, room);
+                    let link_entity = create_link(&mut commands, other, room, color);
+                    room_entities
+                        .links
+                        .insert(RoomEntities::link_key(new_room.id, *link), link_entity);
                 }
-                commands.entity(e).despawn();
             }
         }
     }
 }
 
-fn find_closest(existing_points: &[(f32, f32)], ref_point: &(f32, f32)) -> (Vec2, f32) {
-    let mut closest: Vec2 = Vec2::new(0f32, 0f32);
-    let mut distance = f32::MAX;
-    let ref_point = Vec2::new(ref_point.0, ref_point.1);
-    for p in existing_points.iter() {
-        let new_closest = Vec2::new(p.0, p.1);
-        let new_distance = new_closest.distance_squared(ref_point);
-        if new_distance < distance {
-            closest = new_closest;
-            distance = new_distance;
+/// Forgets the rooms too far from the player once they arrived in a room,
+/// see [`MapConfiguration::culling`].
+fn cull_far_rooms(
+    mut commands: Commands,
+    map_configuration: Res<MapConfiguration>,
+    position: Res<MapPosition>,
+    mut map: ResMut<MapDef>,
+    mut room_entities: ResMut<RoomEntities>,
+    q_battle_graphics: Query<&BattleGraphicRef>,
+) {
+    if !position.is_changed() || position.will_move.is_some() {
+        return;
+    }
+    if !map_configuration.culling.is_enabled() {
+        return;
+    }
+    let culled = match cull(&mut map, position.pos_id, &map_configuration.culling) {
+        Ok(culled) => culled,
+        Err(err) => {
+            error!("Could not cull rooms: {}", err);
+            return;
+        }
+    };
+    for id in culled {
+        if let Some(entity) = room_entities.entities.remove(&id) {
+            if let Ok(graphic) = q_battle_graphics.get(entity) {
+                commands.entity(graphic.entity).despawn();
+            }
+            commands.entity(entity).despawn();
+        }
+    }
+    let entities = &room_entities.entities;
+    let removed_links: Vec<(RoomId, RoomId)> = room_entities
+        .links
+        .keys()
+        .filter(|(a, b)| !entities.contains_key(a) || !entities.contains_key(b))
+        .copied()
+        .collect();
+    for key in removed_links {
+        if let Some(link) = room_entities.links.remove(&key) {
+            commands.entity(link).despawn();
+        }
+    }
+}
+
+/// Reports broken connections as soon as the map changes, only in debug builds.
+#[cfg(debug_assertions)]
+fn validate_map(map: Res<MapDef>) {
+    if !map.is_changed() {
+        return;
+    }
+    if let Err(errors) = map.validate() {
+        for err in errors {
+            error!("Invalid map: {}", err);
         }
     }
-    (closest, distance)
 }
 
 fn update_player_position(
+    mut commands: Commands,
+    time: Res<Time>,
     position: Res<MapPosition>,
     map: Res<MapDef>,
-    mut q_pos: Query<&mut Transform, With<PlayerPositionDisplay>>,
+    mut q_pos: Query<(Entity, &mut Transform, Option<&KnockBack>), With<PlayerPositionDisplay>>,
 ) {
-    if let Some(room_target) = map.rooms.get_key_value(&position.pos_id) {
-        let target_position =
-            Vec2::new(room_target.1.position.0, room_target.1.position.1).extend(0.0);
-        for mut t in q_pos.iter_mut() {
-            t.translation = target_position;
+    if let Ok(room_target) = map.room(position.pos_id) {
+        let target_position = Vec2::new(room_target.position.0, room_target.position.1).extend(0.0);
+        for (e, mut t, knock_back) in q_pos.iter_mut() {
+            if knock_back.is_none() {
+                t.translation = target_position;
+                continue;
+            }
+            t.translation = math_utils::move_towards(
+                t.translation,
+                target_position,
+                KNOCK_BACK_SPEED * time.delta_seconds(),
+            );
+            if t.translation == target_position {
+                commands.entity(e).remove::<KnockBack>();
+            }
         }
     }
 }
 
-fn create_link(commands: &mut Commands, from: &Room, to: &Room) {
+/// Links are tinted by the biome of `room` when biomes are enabled.
+fn link_color(map_configuration: &MapConfiguration, biomes: &Biomes, room: &Room) -> Color {
+    if !map_configuration.biomes {
+        return Color::CYAN;
+    }
+    let [r, g, b] = biomes.biome_at(&room.position).tint;
+    Color::rgb(r, g, b)
+}
+
+fn create_link(commands: &mut Commands, from: &Room, to: &Room, color: Color) -> Entity {
     let character = GeometryBuilder::build_as(
         &Line(
             Vec2::new(from.position.0, from.position.1),
             Vec2::new(to.position.0, to.position.1),
         ),
-        ShapeColors::outlined(Color::CYAN, Color::BLACK),
+        ShapeColors::outlined(color, Color::BLACK),
         DrawMode::Outlined {
             fill_options: FillOptions::default(),
             outline_options: StrokeOptions::default().with_line_width(2.0),
         },
         Transform::from_xyz(0.0, 0.0, 0.0),
     );
-    commands.spawn_bundle(character);
+    commands.spawn_bundle(character).id()
 }
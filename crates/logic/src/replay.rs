@@ -0,0 +1,175 @@
+use bevy::prelude::*;
+use map_gen::generator::is_supported_version;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    combat::{CombatAction, CombatInputs},
+    map_graph::{
+        GeneratorVersion, MapConfiguration, RandomDeterministic, RoomChanceWeights, RoomId,
+        UserInput, UserInputs,
+    },
+    room_definitions::RoomDefinitions,
+    save::SaveError,
+};
+
+pub const REPLAY_PATH: &str = "replay.ron";
+
+/// Game time simulated by each [`GameTick`], whatever the frame rate, so replays play out
+/// the same as the recorded run.
+pub const SECONDS_PER_TICK: f32 = 1f32 / 60f32;
+
+/// Number of game updates since the run started.
+#[derive(Default, Clone, Copy)]
+pub struct GameTick(pub u64);
+
+impl GameTick {
+    /// Game time elapsed since `tick`.
+    pub fn seconds_since(&self, tick: u64) -> f32 {
+        self.0.saturating_sub(tick) as f32 * SECONDS_PER_TICK
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct RecordedMove {
+    pub tick: u64,
+    pub room: RoomId,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct RecordedAction {
+    pub tick: u64,
+    pub action: CombatAction,
+}
+
+/// Everything needed to reproduce a run: its generator version, its seed, its tuning values
+/// and every resolved move and combat action. The room definitions are too large to be
+/// recorded, only their fingerprint is, to refuse replaying with other definitions.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RunRecording {
+    /// 0 for runs recorded before generator versions, which can't be reproduced.
+    #[serde(default)]
+    pub generator_version: u32,
+    /// [`RoomDefinitions::fingerprint`], 0 for runs recorded before it, which can't be checked.
+    #[serde(default)]
+    pub definitions_fingerprint: u64,
+    pub seed: u64,
+    pub configuration: MapConfiguration,
+    pub room_weights: Vec<usize>,
+    pub rooms_to_create_on_move: u32,
+    pub moves: Vec<RecordedMove>,
+    #[serde(default)]
+    pub actions: Vec<RecordedAction>,
+}
+
+impl RunRecording {
+    pub fn new(
+        generator_version: u32,
+        seed: u64,
+        configuration: &MapConfiguration,
+        definitions: &RoomDefinitions,
+        chances: &RoomChanceWeights,
+    ) -> Self {
+        Self {
+            generator_version,
+            definitions_fingerprint: definitions.fingerprint(),
+            seed,
+            configuration: configuration.clone(),
+            room_weights: chances.weights.clone(),
+            rooms_to_create_on_move: chances.rooms_to_create_on_move,
+            moves: vec![],
+            actions: vec![],
+        }
+    }
+
+    /// Restores the generator version, seed and tuning values the run was recorded with.
+    /// Nothing is changed if the run was recorded with other room definitions than
+    /// `definitions`, or if the recorded weights can't generate any room.
+    pub fn apply_configuration(
+        &self,
+        definitions: &RoomDefinitions,
+        generator_version: &mut GeneratorVersion,
+        random: &mut RandomDeterministic,
+        configuration: &mut MapConfiguration,
+        chances: &mut RoomChanceWeights,
+    ) -> Result<(), SaveError> {
+        // The chances are derived from the definitions, so the weights match the room types.
+        if self.definitions_fingerprint != definitions.fingerprint() {
+            return Err(SaveError::OtherRoomDefinitions);
+        }
+        chances.set_weights(self.room_weights.clone())?;
+        generator_version.0 = self.generator_version;
+        random.set_seed(self.seed);
+        *configuration = self.configuration.clone();
+        chances.rooms_to_create_on_move = self.rooms_to_create_on_move;
+        Ok(())
+    }
+}
+
+/// Present while a recorded run is replayed, player clicks are ignored.
+pub struct ReplayPlayback {
+    moves: Vec<RecordedMove>,
+    next: usize,
+    actions: Vec<RecordedAction>,
+    next_action: usize,
+}
+
+impl ReplayPlayback {
+    pub fn new(recording: &RunRecording) -> Self {
+        Self {
+            moves: recording.moves.clone(),
+            next: 0,
+            actions: recording.actions.clone(),
+            next_action: 0,
+        }
+    }
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.moves.len() && self.next_action >= self.actions.len()
+    }
+}
+
+pub fn save_replay_to_disk(path: &str, recording: &RunRecording) -> Result<(), SaveError> {
+    let content = ron::ser::to_string_pretty(recording, ron::ser::PrettyConfig::default())?;
+    std::fs::write(path, content)?;
+    Ok(())
+}
+
+pub fn load_replay_from_disk(path: &str) -> Result<RunRecording, SaveError> {
+    let content = std::fs::read_to_string(path)?;
+    let recording: RunRecording = ron::de::from_str(&content)?;
+    if !is_supported_version(recording.generator_version) {
+        return Err(SaveError::UnsupportedGenerator(recording.generator_version));
+    }
+    Ok(recording)
+}
+
+pub fn advance_game_tick(mut tick: ResMut<GameTick>) {
+    tick.0 += 1;
+}
+
+/// Pushes the recorded moves into [`UserInputs`] and the recorded combat actions into
+/// [`CombatInputs`] once their tick is reached.
+pub fn feed_replay_inputs(
+    tick: Res<GameTick>,
+    playback: Option<ResMut<ReplayPlayback>>,
+    mut inputs: ResMut<UserInputs>,
+    mut combat_inputs: ResMut<CombatInputs>,
+) {
+    let mut playback = match playback {
+        Some(p) => p,
+        None => return,
+    };
+    while let Some(next) = playback.moves.get(playback.next).copied() {
+        if next.tick > tick.0 {
+            break;
+        }
+        inputs.list.push(UserInput::MoveTo(next.room));
+        playback.next += 1;
+    }
+    while let Some(next) = playback.actions.get(playback.next_action).copied() {
+        if next.tick > tick.0 {
+            break;
+        }
+        combat_inputs.list.push(next.action);
+        playback.next_action += 1;
+    }
+}
@@ -0,0 +1,133 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+
+use crate::{
+    map_graph::{Biomes, RoomChanceWeights},
+    shapes::{ColorMaterial, ShapeMeshes},
+};
+pub use map_gen::room_definitions::{RoomDefinitions, RoomEffect, RoomTypeDefinition};
+
+#[derive(TypeUuid)]
+#[uuid = "5b0b7a3e-2f4c-4d37-9a0e-7d1c6b8f2a41"]
+pub struct RoomDefinitionsAsset(pub RoomDefinitions);
+
+/// Loads `.rooms.ron` files.
+#[derive(Default)]
+pub struct RoomDefinitionsLoader;
+
+impl AssetLoader for RoomDefinitionsLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let definitions: RoomDefinitions = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(RoomDefinitionsAsset(definitions)));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["rooms.ron"]
+    }
+}
+
+/// Keeps the room definitions asset loaded, so its changes are watched.
+pub struct RoomDefinitionsHandle(pub Handle<RoomDefinitionsAsset>);
+
+pub struct RoomDefinitionsPlugin;
+
+impl Plugin for RoomDefinitionsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_asset::<RoomDefinitionsAsset>()
+            .init_asset_loader::<RoomDefinitionsLoader>()
+            .insert_resource(RoomDefinitions::default())
+            .add_startup_system(load_room_definitions.system())
+            .add_system(apply_room_definitions.system())
+            .add_system(update_room_materials.system());
+    }
+}
+
+fn load_room_definitions(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut definitions: ResMut<Assets<RoomDefinitionsAsset>>,
+) {
+    #[cfg(not(target_arch = "wasm32"))]
+    let handle = asset_server.load("../../logic/assets/default.rooms.ron");
+    #[cfg(target_arch = "wasm32")]
+    let handle = match ron::de::from_str(include_str!("../assets/default.rooms.ron")) {
+        Ok(loaded) => definitions.add(RoomDefinitionsAsset(loaded)),
+        Err(err) => {
+            error!("Invalid room definitions: {}", err);
+            return;
+        }
+    };
+    commands.insert_resource(RoomDefinitionsHandle(handle));
+}
+
+/// Replaces the room definitions and the chances derived from them each time the asset
+/// is loaded or modified. Rooms already created keep their type.
+fn apply_room_definitions(
+    mut events: EventReader<AssetEvent<RoomDefinitionsAsset>>,
+    assets: Res<Assets<RoomDefinitionsAsset>>,
+    mut definitions: ResMut<RoomDefinitions>,
+    mut chances: ResMut<RoomChanceWeights>,
+    mut biomes: ResMut<Biomes>,
+) {
+    for event in events.iter() {
+        let handle = match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => handle,
+            AssetEvent::Removed { .. } => continue,
+        };
+        let loaded = match assets.get(handle) {
+            Some(loaded) => &loaded.0,
+            None => continue,
+        };
+        let derived = loaded
+            .chances(chances.rooms_to_create_on_move)
+            .and_then(|new_chances| Ok((Biomes::new(loaded, &new_chances)?, new_chances)));
+        match derived {
+            Ok((new_biomes, new_chances)) => {
+                *chances = new_chances;
+                *biomes = new_biomes;
+            }
+            Err(err) => {
+                error!("Room definitions ignored: {}", err);
+                continue;
+            }
+        }
+        *definitions = loaded.clone();
+        info!("Loaded {} room types", definitions.rooms.len());
+    }
+}
+
+fn update_room_materials(
+    definitions: Res<RoomDefinitions>,
+    shapes: Option<ResMut<ShapeMeshes>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let mut shapes = match shapes {
+        Some(shapes) => shapes,
+        None => return,
+    };
+    if !definitions.is_changed() && !shapes.mat_rooms.is_empty() {
+        return;
+    }
+    shapes.mat_rooms = definitions
+        .rooms
+        .iter()
+        .map(|r| {
+            let [red, green, blue] = r.color;
+            let material = materials.add(ColorMaterial {
+                color: Color::rgb(red, green, blue),
+            });
+            (r.room_type(), material)
+        })
+        .collect();
+}
@@ -0,0 +1,224 @@
+use std::fmt;
+
+use bevy::prelude::*;
+use map_gen::generator::{is_supported_version, GENERATOR_VERSION};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    combat::{Battle, Encounter, IsDirty, PlayerStats},
+    danger::{DangerSpeedModifier, DangerZone, GrowDangerZone, SpawnDangerZoneCommand},
+    map_graph::{
+        spawn_room_entities, Coins, GeneratorVersion, MapDef, MapPosition, MoveQueue,
+        RandomDeterministic, RoomId, WeightsError,
+    },
+    replay::{GameTick, RunRecording},
+    text_feedback::TextFeedbackSpawn,
+};
+
+/// Bumped whenever [`SaveGame`] changes in an incompatible way.
+pub const SAVE_VERSION: u32 = 8;
+pub const SAVE_PATH: &str = "savegame.ron";
+
+/// Full state of a run, enough to resume it where it stopped. Rooms created after loading
+/// use the generator version of `recording`.
+#[derive(Serialize, Deserialize)]
+pub struct SaveGame {
+    pub version: u32,
+    pub map: MapDef,
+    pub position: RoomId,
+    pub coins: u32,
+    pub player: PlayerStats,
+    pub danger_speed_multiplier: f32,
+    pub danger_zones: Vec<DangerZoneSave>,
+    pub battles: Vec<(RoomId, Battle)>,
+    pub random: RandomDeterministic,
+    pub tick: u64,
+    pub recording: RunRecording,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DangerZoneSave {
+    pub position: (f32, f32),
+    pub size: f32,
+    pub radius_increase_per_second: f32,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    Format(ron::Error),
+    UnsupportedVersion(u32),
+    /// Recorded with a generator version this build can't reproduce.
+    UnsupportedGenerator(u32),
+    /// The recorded room weights can't generate rooms.
+    RoomWeights(WeightsError),
+    /// Recorded with other room definitions than the loaded ones.
+    OtherRoomDefinitions,
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(e) => write!(f, "io error: {}", e),
+            SaveError::Format(e) => write!(f, "invalid save: {}", e),
+            SaveError::UnsupportedVersion(v) => {
+                write!(
+                    f,
+                    "unsupported save version {} (expected {})",
+                    v, SAVE_VERSION
+                )
+            }
+            SaveError::UnsupportedGenerator(v) => {
+                write!(
+                    f,
+                    "unsupported generator version {} (supported up to {})",
+                    v, GENERATOR_VERSION
+                )
+            }
+            SaveError::RoomWeights(e) => write!(f, "{}", e),
+            SaveError::OtherRoomDefinitions => {
+                write!(f, "recorded with other room definitions")
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for SaveError {
+    fn from(e: std::io::Error) -> Self {
+        SaveError::Io(e)
+    }
+}
+
+impl From<ron::Error> for SaveError {
+    fn from(e: ron::Error) -> Self {
+        SaveError::Format(e)
+    }
+}
+
+impl From<WeightsError> for SaveError {
+    fn from(e: WeightsError) -> Self {
+        SaveError::RoomWeights(e)
+    }
+}
+
+pub fn save_to_disk(path: &str, save: &SaveGame) -> Result<(), SaveError> {
+    let content = ron::ser::to_string_pretty(save, ron::ser::PrettyConfig::default())?;
+    std::fs::write(path, content)?;
+    Ok(())
+}
+
+pub fn load_from_disk(path: &str) -> Result<SaveGame, SaveError> {
+    let content = std::fs::read_to_string(path)?;
+    let save: SaveGame = ron::de::from_str(&content)?;
+    if save.version != SAVE_VERSION {
+        return Err(SaveError::UnsupportedVersion(save.version));
+    }
+    if !is_supported_version(save.recording.generator_version) {
+        return Err(SaveError::UnsupportedGenerator(
+            save.recording.generator_version,
+        ));
+    }
+    Ok(save)
+}
+
+/// Spawn an entity with this component to save the current run.
+pub struct SaveGameCommand;
+
+/// When present while entering [`crate::AppState::Loading`], the run is restored from it
+/// instead of creating a new map.
+pub struct PendingLoad(pub SaveGame);
+
+pub fn save_game(
+    mut commands: Commands,
+    q_save: Query<Entity, With<SaveGameCommand>>,
+    map: Res<MapDef>,
+    position: Res<MapPosition>,
+    coins: Res<Coins>,
+    player: Res<PlayerStats>,
+    danger_speed_modifier: Res<DangerSpeedModifier>,
+    random: Res<RandomDeterministic>,
+    tick: Res<GameTick>,
+    recording: Res<RunRecording>,
+    encounter: Option<Res<Encounter>>,
+    dangers: Query<(&Transform, &DangerZone, &GrowDangerZone)>,
+    battles: Query<(&RoomId, &Battle)>,
+) {
+    for e in q_save.iter() {
+        commands.entity(e).despawn();
+        let save = SaveGame {
+            version: SAVE_VERSION,
+            map: map.clone(),
+            // Saving during a battle retreats from it.
+            position: encounter
+                .as_ref()
+                .map_or(position.pos_id, |encounter| encounter.from),
+            coins: coins.amount,
+            player: player.clone(),
+            danger_speed_multiplier: danger_speed_modifier.multiplier,
+            danger_zones: dangers
+                .iter()
+                .map(|(t, d, g)| DangerZoneSave {
+                    position: (t.translation.x, t.translation.y),
+                    size: d.size,
+                    radius_increase_per_second: g.radius_increase_per_second,
+                })
+                .collect(),
+            battles: battles.iter().map(|(id, b)| (*id, b.clone())).collect(),
+            random: random.clone(),
+            tick: tick.0,
+            recording: recording.clone(),
+        };
+        let text = match save_to_disk(SAVE_PATH, &save) {
+            Ok(()) => "Saved".to_string(),
+            Err(err) => format!("Save failed\n{}", err),
+        };
+        commands.spawn().insert(TextFeedbackSpawn {
+            text,
+            pos: Vec2::ZERO,
+        });
+    }
+}
+
+/// Rebuilds the resources and entities of a saved run, the rooms are displayed by
+/// `init_display_map` when entering the game.
+/// The tuning values must be restored beforehand with
+/// [`crate::replay::RunRecording::apply_configuration`].
+pub fn restore_run(
+    commands: &mut Commands,
+    save: &SaveGame,
+    random: &mut RandomDeterministic,
+    generator_version: &mut GeneratorVersion,
+) {
+    *random = save.random.clone();
+    generator_version.0 = save.recording.generator_version;
+    let room_entities = spawn_room_entities(commands, &save.map);
+    for (id, battle) in save.battles.iter() {
+        if let Some(entity) = room_entities.entities.get(id) {
+            commands
+                .entity(*entity)
+                .insert(battle.clone())
+                .insert(IsDirty);
+        }
+    }
+    for danger in save.danger_zones.iter() {
+        commands.spawn().insert(SpawnDangerZoneCommand {
+            position: danger.position.into(),
+            size: danger.size,
+            radius_increase_per_second: danger.radius_increase_per_second,
+        });
+    }
+    commands.insert_resource(save.map.clone());
+    commands.insert_resource(room_entities);
+    commands.insert_resource(MapPosition {
+        pos_id: save.position,
+        will_move: None,
+    });
+    commands.insert_resource(Coins { amount: save.coins });
+    commands.insert_resource(save.player.clone());
+    commands.insert_resource(MoveQueue::default());
+    commands.insert_resource(DangerSpeedModifier {
+        multiplier: save.danger_speed_multiplier,
+    });
+    commands.insert_resource(GameTick(save.tick));
+    commands.insert_resource(save.recording.clone());
+}
@@ -7,8 +7,11 @@ use bevy::{
         renderer::RenderResources,
         shader::{ShaderStage, ShaderStages},
     },
+    utils::HashMap,
 };
 
+use crate::map_graph::RoomType;
+
 #[derive(RenderResources, Default, TypeUuid)]
 #[uuid = "1e08866c-0b8a-437e-8bce-37733b25127e"]
 pub struct ColorMaterial {
@@ -29,9 +32,9 @@ pub struct ShapeMeshes {
     pub pipeline_circle_gauge: Handle<PipelineDescriptor>,
     pub mat_white: Handle<ColorMaterial>,
     pub mat_orange: Handle<ColorMaterial>,
-    pub mat_fuchsia: Handle<ColorMaterial>,
-    pub mat_green: Handle<ColorMaterial>,
     pub mat_gray: Handle<ColorMaterial>,
+    /// One per room type, see [`crate::room_definitions`].
+    pub mat_rooms: HashMap<RoomType, Handle<ColorMaterial>>,
     pub mat_circle_gauge: Handle<CircleGaugeMaterial>,
 }
 
@@ -135,16 +138,11 @@ pub fn init_shapes(
         mat_white: materials_color.add(ColorMaterial {
             color: Color::WHITE,
         }),
-        mat_green: materials_color.add(ColorMaterial {
-            color: Color::GREEN,
-        }),
         mat_orange: materials_color.add(ColorMaterial {
             color: Color::ORANGE_RED,
         }),
-        mat_fuchsia: materials_color.add(ColorMaterial {
-            color: Color::FUCHSIA,
-        }),
         mat_gray: materials_color.add(ColorMaterial { color: Color::GRAY }),
+        mat_rooms: HashMap::default(),
         pipeline_circle_gauge: pipeline_circle_gauge_handle,
         mat_circle_gauge: materials_circle_gauge.add(CircleGaugeMaterial {
             ratio: 0.5f32,
@@ -0,0 +1,199 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    math::atan2,
+    room_chances::{RoomChanceWeights, WeightsError},
+    room_definitions::RoomDefinitions,
+};
+
+/// A region of the world with its own room chances.
+#[derive(Clone)]
+pub struct Biome {
+    pub name: String,
+    pub chances: RoomChanceWeights,
+    /// Applied to the growth of danger zones spawned in this biome.
+    pub danger_speed_multiplier: f32,
+    /// RGB, each component between 0 and 1.
+    pub tint: [f32; 3],
+}
+
+/// Values of a room type replaced in a biome, the values not given are kept.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RoomOverride {
+    /// Name of the room type.
+    pub name: String,
+    #[serde(default)]
+    pub weight: Option<usize>,
+    #[serde(default)]
+    pub battle_chance: Option<f64>,
+    #[serde(default)]
+    pub max_rooms_create: Option<u32>,
+}
+
+/// A biome, as declared by designers next to the room types.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BiomeDefinition {
+    pub name: String,
+    /// RGB, each component between 0 and 1.
+    pub tint: [f32; 3],
+    pub danger_speed_multiplier: f32,
+    /// Room types generated differently in this biome.
+    #[serde(default)]
+    pub rooms: Vec<RoomOverride>,
+}
+
+impl BiomeDefinition {
+    /// `base` with the overrides of this biome. Overrides of unknown room types are ignored,
+    /// and if they leave no weight the weights of `base` are kept.
+    pub fn chances(&self, base: &RoomChanceWeights) -> Result<RoomChanceWeights, WeightsError> {
+        let mut chances = base.clone();
+        let mut weights = base.weights.clone();
+        for room in self.rooms.iter() {
+            let index = match chances
+                .definitions
+                .iter()
+                .position(|d| d.type_room.0 == room.name)
+            {
+                Some(index) => index,
+                None => continue,
+            };
+            let definition = &mut chances.definitions[index];
+            if let Some(weight) = room.weight {
+                weights[index] = weight;
+            }
+            if let Some(battle_chance) = room.battle_chance {
+                definition.battle_chance = battle_chance;
+            }
+            if let Some(max_rooms_create) = room.max_rooms_create {
+                definition.max_rooms_create = max_rooms_create;
+            }
+        }
+        if weights.iter().any(|w| *w > 0) {
+            chances.set_weights(weights)?;
+        }
+        Ok(chances)
+    }
+}
+
+/// Divides the world in angular sectors around the origin, one per biome.
+#[derive(Clone)]
+pub struct Biomes {
+    pub biomes: Vec<Biome>,
+    /// Angle in radians where the first sector starts.
+    pub rotation: f32,
+}
+
+impl Biomes {
+    /// The biomes of `definitions`, changing the room chances of `base`. Without any biome
+    /// declared, the whole world is a single biome using `base`.
+    pub fn new(
+        definitions: &RoomDefinitions,
+        base: &RoomChanceWeights,
+    ) -> Result<Self, WeightsError> {
+        let mut biomes = definitions
+            .biomes
+            .iter()
+            .map(|biome| {
+                Ok(Biome {
+                    name: biome.name.clone(),
+                    chances: biome.chances(base)?,
+                    danger_speed_multiplier: biome.danger_speed_multiplier,
+                    tint: biome.tint,
+                })
+            })
+            .collect::<Result<Vec<Biome>, WeightsError>>()?;
+        if biomes.is_empty() {
+            biomes.push(Biome {
+                name: "World".to_string(),
+                chances: base.clone(),
+                danger_speed_multiplier: 1f32,
+                tint: [0f32, 1f32, 1f32],
+            });
+        }
+        Ok(Self {
+            biomes,
+            rotation: 0f32,
+        })
+    }
+
+    /// Index in [`Biomes::biomes`] of the sector containing `position`.
+    pub fn index_at(&self, position: &(f32, f32)) -> usize {
+        let full_turn = 2f32 * std::f32::consts::PI;
+        let angle = (atan2(position.1, position.0) - self.rotation).rem_euclid(full_turn);
+        let sector = (angle / full_turn * self.biomes.len() as f32) as usize;
+        // Rounding can reach the end of the turn.
+        sector.min(self.biomes.len() - 1)
+    }
+
+    pub fn biome_at(&self, position: &(f32, f32)) -> &Biome {
+        &self.biomes[self.index_at(position)]
+    }
+}
+
+impl Default for Biomes {
+    fn default() -> Self {
+        Self::new(&RoomDefinitions::default(), &RoomChanceWeights::default())
+            .expect("default biomes have weights")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sectors_follow_angle() {
+        let biomes = Biomes::default();
+        assert_eq!(biomes.index_at(&(10f32, 1f32)), 0);
+        assert_eq!(biomes.index_at(&(-1f32, 10f32)), 1);
+        assert_eq!(biomes.index_at(&(-10f32, -1f32)), 2);
+        assert_eq!(biomes.index_at(&(1f32, -10f32)), 3);
+        assert_eq!(biomes.index_at(&(0f32, 0f32)), 0);
+    }
+    #[test]
+    fn rotation_shifts_sectors() {
+        let biomes = Biomes {
+            rotation: std::f32::consts::FRAC_PI_2,
+            ..Biomes::default()
+        };
+        assert_eq!(biomes.index_at(&(-1f32, 10f32)), 0);
+        assert_eq!(biomes.index_at(&(10f32, 1f32)), 3);
+    }
+    #[test]
+    fn biomes_override_room_types() {
+        let mut definitions = RoomDefinitions {
+            biomes: vec![BiomeDefinition {
+                name: "Arena".to_string(),
+                tint: [1f32, 0f32, 0f32],
+                danger_speed_multiplier: 2f32,
+                rooms: vec![
+                    RoomOverride {
+                        name: "Safe".to_string(),
+                        weight: Some(0),
+                        battle_chance: Some(1f64),
+                        max_rooms_create: None,
+                    },
+                    RoomOverride {
+                        name: "Unknown".to_string(),
+                        weight: Some(10),
+                        battle_chance: None,
+                        max_rooms_create: None,
+                    },
+                ],
+            }],
+            ..RoomDefinitions::default()
+        };
+        let base = definitions.chances(5).unwrap();
+        let biomes = Biomes::new(&definitions, &base).unwrap();
+        assert_eq!(biomes.biomes.len(), 1);
+        let chances = &biomes.biome_at(&(10f32, 0f32)).chances;
+        assert_eq!(chances.weights, vec![30, 0, 40, 20]);
+        assert_eq!(chances.definitions[1].battle_chance, 1f64);
+        assert_eq!(chances.definitions[1].max_rooms_create, 2);
+
+        definitions.biomes.clear();
+        let biomes = Biomes::new(&definitions, &base).unwrap();
+        assert_eq!(biomes.biomes.len(), 1);
+        assert_eq!(biomes.biomes[0].chances.weights, base.weights);
+    }
+}
@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{connection::Connections, culling::CullSettings, generator::Layout};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MapConfiguration {
+    pub start_with_danger_zone: bool,
+    pub speed_gain_danger: f32,
+    pub speed_init_danger: f32,
+
+    pub weight_room_danger: f32,
+    pub weight_room_shop: f32,
+    pub weight_room_normal: f32,
+
+    /// Missing from runs recorded before culling existed, they are replayed without it.
+    #[serde(default)]
+    pub culling: CullSettings,
+    /// Rooms follow the biome at their position, see [`crate::biome::Biomes`].
+    #[serde(default)]
+    pub biomes: bool,
+    /// New links never cross existing ones, see [`crate::generator::RoomFactory`].
+    #[serde(default)]
+    pub planar_links: bool,
+    /// Rooms a new room links to, besides the room it expands from.
+    #[serde(default)]
+    pub connections: Connections,
+    /// Chance to create each link of [`MapConfiguration::connections`], 0 makes the map a tree.
+    #[serde(default = "full_loop_density")]
+    pub loop_density: f32,
+    #[serde(default)]
+    pub layout: Layout,
+}
+
+fn full_loop_density() -> f32 {
+    1f32
+}
+
+impl Default for MapConfiguration {
+    fn default() -> Self {
+        Self {
+            start_with_danger_zone: true,
+            speed_gain_danger: 0.1f32,
+            speed_init_danger: 10f32,
+            weight_room_danger: Default::default(),
+            weight_room_shop: Default::default(),
+            weight_room_normal: Default::default(),
+            culling: CullSettings::default(),
+            biomes: false,
+            planar_links: false,
+            connections: Connections::default(),
+            loop_density: full_loop_density(),
+            layout: Layout::default(),
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    map::{MapDef, RoomId},
+    poisson::distance_squared,
+};
+
+/// Chooses which rooms a new room links to, besides the room it expands from.
+///
+/// More links make more loops, so more alternative routes.
+pub trait ConnectionStrategy {
+    /// Rooms closer than `max_distance` to `new_room` it may link to, preferred first.
+    /// `from` is already linked and must not be returned.
+    fn candidates(
+        &self,
+        map: &MapDef,
+        from: RoomId,
+        new_room: RoomId,
+        max_distance: f32,
+    ) -> Vec<RoomId>;
+
+    /// Most links made from the candidates, `None` to try all of them.
+    fn max_links(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Links to the closest room only.
+pub struct ClosestRoom;
+
+impl ConnectionStrategy for ClosestRoom {
+    fn candidates(
+        &self,
+        map: &MapDef,
+        from: RoomId,
+        new_room: RoomId,
+        max_distance: f32,
+    ) -> Vec<RoomId> {
+        nearby_rooms(map, from, new_room, max_distance)
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    fn max_links(&self) -> Option<usize> {
+        Some(1)
+    }
+}
+
+/// Links to the `k` closest rooms.
+pub struct KNearest(pub usize);
+
+impl ConnectionStrategy for KNearest {
+    fn candidates(
+        &self,
+        map: &MapDef,
+        from: RoomId,
+        new_room: RoomId,
+        max_distance: f32,
+    ) -> Vec<RoomId> {
+        ClosestRoom.candidates(map, from, new_room, max_distance)
+    }
+
+    fn max_links(&self) -> Option<usize> {
+        Some(self.0)
+    }
+}
+
+/// Links to rooms with no third room closer to both of them than they are to each other.
+pub struct RelativeNeighbourhood;
+
+impl ConnectionStrategy for RelativeNeighbourhood {
+    fn candidates(
+        &self,
+        map: &MapDef,
+        from: RoomId,
+        new_room: RoomId,
+        max_distance: f32,
+    ) -> Vec<RoomId> {
+        let position = match map.room(new_room) {
+            Ok(room) => room.position,
+            Err(_) => return vec![],
+        };
+        nearby_rooms(map, from, new_room, max_distance)
+            .into_iter()
+            .filter(|(candidate, distance)| {
+                let candidate_position = map.room(*candidate).map_or(position, |r| r.position);
+                // A closer third room is closer than `distance` to the new room.
+                !map.spatial()
+                    .within_radius(&position, distance.sqrt())
+                    .into_iter()
+                    .any(|(other, to_new)| {
+                        other != *candidate
+                            && other != new_room
+                            && to_new < *distance
+                            && matches!(map.room(other), Ok(r)
+                                if distance_squared(&r.position, &candidate_position) < *distance)
+                    })
+            })
+            .map(|(id, _)| id)
+            .collect()
+    }
+}
+
+/// Links to rooms with no third room inside the circle having them as diameter,
+/// this graph is a subset of the Delaunay triangulation.
+pub struct Gabriel;
+
+impl ConnectionStrategy for Gabriel {
+    fn candidates(
+        &self,
+        map: &MapDef,
+        from: RoomId,
+        new_room: RoomId,
+        max_distance: f32,
+    ) -> Vec<RoomId> {
+        let position = match map.room(new_room) {
+            Ok(room) => room.position,
+            Err(_) => return vec![],
+        };
+        nearby_rooms(map, from, new_room, max_distance)
+            .into_iter()
+            .filter(|(candidate, distance)| {
+                let candidate_position = map.room(*candidate).map_or(position, |r| r.position);
+                let center = (
+                    (position.0 + candidate_position.0) / 2f32,
+                    (position.1 + candidate_position.1) / 2f32,
+                );
+                let radius_squared = distance / 4f32;
+                !map.spatial()
+                    .within_radius(&center, radius_squared.sqrt())
+                    .into_iter()
+                    .any(|(other, to_center)| {
+                        other != *candidate && other != new_room && to_center < radius_squared
+                    })
+            })
+            .map(|(id, _)| id)
+            .collect()
+    }
+}
+
+/// Rooms closer than `max_distance` to `new_room`, closest first, with their squared distance.
+fn nearby_rooms(
+    map: &MapDef,
+    from: RoomId,
+    new_room: RoomId,
+    max_distance: f32,
+) -> Vec<(RoomId, f32)> {
+    let position = match map.room(new_room) {
+        Ok(room) => room.position,
+        Err(_) => return vec![],
+    };
+    let mut nearby: Vec<(RoomId, f32)> = map
+        .spatial()
+        .within_radius(&position, max_distance)
+        .into_iter()
+        .filter(|(id, _)| *id != from && *id != new_room)
+        .collect();
+    nearby.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    nearby
+}
+
+/// Serializable choice of [`ConnectionStrategy`], for [`crate::configuration::MapConfiguration`].
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Connections {
+    ClosestRoom,
+    KNearest(usize),
+    RelativeNeighbourhood,
+    Gabriel,
+}
+
+impl Connections {
+    pub fn strategy(&self) -> Box<dyn ConnectionStrategy> {
+        match self {
+            Connections::ClosestRoom => Box::new(ClosestRoom),
+            Connections::KNearest(k) => Box::new(KNearest(*k)),
+            Connections::RelativeNeighbourhood => Box::new(RelativeNeighbourhood),
+            Connections::Gabriel => Box::new(Gabriel),
+        }
+    }
+}
+
+impl Default for Connections {
+    fn default() -> Self {
+        Connections::ClosestRoom
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::map::{Room, RoomType};
+
+    /// The new room 0, the room it expands from 1, and candidates around.
+    ///
+    /// ```text
+    ///     3
+    /// 0    2    4
+    /// 1
+    /// ```
+    fn map() -> MapDef {
+        let mut map = MapDef::default();
+        let positions = [
+            (0f32, 0f32),
+            (0f32, -10f32),
+            (10f32, 0f32),
+            (9f32, 7f32),
+            (20f32, 0f32),
+        ];
+        for (i, position) in positions.iter().enumerate() {
+            map.add_room(RoomId(i), Room::new(*position, RoomType::safe()))
+                .unwrap();
+        }
+        map
+    }
+
+    #[test]
+    fn nearest_candidates_are_sorted() {
+        let map = map();
+        assert_eq!(
+            ClosestRoom.candidates(&map, RoomId(1), RoomId(0), 15f32),
+            vec![RoomId(2), RoomId(3)]
+        );
+        assert_eq!(ClosestRoom.max_links(), Some(1));
+        assert_eq!(KNearest(2).max_links(), Some(2));
+    }
+    #[test]
+    fn proximity_graphs_skip_rooms_behind_others() {
+        let map = map();
+        // 3 is close to 2, but nothing lies in the circle between 0 and 3.
+        assert_eq!(
+            Gabriel.candidates(&map, RoomId(1), RoomId(0), 25f32),
+            vec![RoomId(2), RoomId(3)]
+        );
+        // 2 is closer to both 0 and 3 than they are to each other.
+        assert_eq!(
+            RelativeNeighbourhood.candidates(&map, RoomId(1), RoomId(0), 25f32),
+            vec![RoomId(2)]
+        );
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+use rand::Rng;
+
+use crate::{
+    generator::{is_free, MapGenerator, RoomCreated, RoomFactory, MIN_DISTANCE_BETWEEN_ROOMS},
+    map::{MapDef, MapError, Room, RoomId, RoomType},
+    math::{atan2, polar_offset},
+};
+
+/// Distance between consecutive rooms of a corridor.
+const STEP: f32 = MIN_DISTANCE_BETWEEN_ROOMS * 1.1f32;
+/// Largest random turn of a corridor at each room, in radians.
+const MAX_TURN: f32 = PI / 8f32;
+const BRANCH_CHANCE: f32 = 0.35f32;
+
+/// Corridors going on in the direction they come from, sometimes branching sideways.
+pub struct BranchingCorridors<'a> {
+    factory: RoomFactory<'a>,
+}
+
+impl<'a> BranchingCorridors<'a> {
+    pub fn new(factory: RoomFactory<'a>) -> Self {
+        Self { factory }
+    }
+
+    fn random_turn(&mut self) -> f32 {
+        (self.factory.rng().gen::<f32>() * 2f32 - 1f32) * MAX_TURN
+    }
+}
+
+impl<'a> MapGenerator for BranchingCorridors<'a> {
+    /// A safe room at the origin and the start of a corridor.
+    fn create_map(&mut self) -> MapDef {
+        let mut map = MapDef::default();
+        let angle = self.factory.rng().gen::<f32>() * 2f32 * PI;
+        let next = polar_offset(&(0f32, 0f32), STEP, angle);
+        for (i, position) in [(0f32, 0f32), next].iter().enumerate() {
+            map.add_room(RoomId(i), Room::new(*position, RoomType::safe()))
+                .expect("room ids are created in order");
+        }
+        map.connect(RoomId(0), RoomId(1)).expect("both rooms exist");
+        map
+    }
+
+    fn expand(
+        &mut self,
+        map: &mut MapDef,
+        from_room_id: RoomId,
+    ) -> Result<Vec<RoomCreated>, MapError> {
+        let from = map.room(from_room_id)?;
+        let position = from.position;
+        // The first remaining link leads to the room this one was created from.
+        let heading = match from.connections().first().map(|c| map.room(*c)) {
+            Some(Ok(previous)) => atan2(
+                position.1 - previous.position.1,
+                position.0 - previous.position.0,
+            ),
+            _ => self.factory.rng().gen::<f32>() * 2f32 * PI,
+        };
+        let mut angles = vec![heading + self.random_turn()];
+        if self.factory.rng().gen::<f32>() < BRANCH_CHANCE {
+            let side = if self.factory.rng().gen::<bool>() {
+                1f32
+            } else {
+                -1f32
+            };
+            angles.push(heading + side * PI / 2f32 + self.random_turn());
+        }
+        angles.truncate(self.factory.rooms_to_create_on_move() as usize);
+
+        let mut created = vec![];
+        let mut duplicates = HashMap::default();
+        for angle in angles {
+            let new_position = polar_offset(&position, STEP, angle);
+            if !is_free(map, &new_position)
+                || !self.factory.can_link(map, from_room_id, &new_position)
+            {
+                continue;
+            }
+            let room = match self.factory.pick_room(&new_position, &mut duplicates) {
+                Some(room) => room,
+                None => continue,
+            };
+            // Corridors passing close to each other may join.
+            created.push(self.factory.add_room(
+                map,
+                from_room_id,
+                new_position,
+                room,
+                STEP * 1.2f32,
+            )?);
+        }
+        Ok(created)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{random::RandomDeterministic, room_chances::RoomChanceWeights};
+
+    #[test]
+    fn corridors_create_few_rooms_per_move() {
+        let mut random = RandomDeterministic::new(2);
+        let chances = RoomChanceWeights::default();
+        let mut generator = BranchingCorridors::new(RoomFactory::new(&mut random, &chances));
+        let mut map = generator.create_map();
+        let mut position = RoomId(1);
+        for _ in 0..10 {
+            let created = generator.expand(&mut map, position).unwrap();
+            assert!(created.len() <= 2);
+            match created.first() {
+                Some(room) => position = room.id,
+                None => break,
+            }
+        }
+        assert!(map.len() > 5);
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    map::{MapDef, MapError, RoomId},
+    poisson::distance_squared,
+};
+
+/// Limits past which rooms are forgotten, `None` disables a limit.
+///
+/// The default disables culling, so runs recorded before culling existed replay the same.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CullSettings {
+    /// Number of connections to walk from the player to reach a room.
+    pub max_graph_distance: Option<u32>,
+    pub max_world_distance: Option<f32>,
+}
+
+impl CullSettings {
+    pub fn is_enabled(&self) -> bool {
+        self.max_graph_distance.is_some() || self.max_world_distance.is_some()
+    }
+}
+
+/// Rooms to forget when the player stands in `from`.
+///
+/// Rooms past a limit are culled, then rooms which could only be reached through culled rooms,
+/// so the remaining map stays connected. `from` is never culled.
+pub fn rooms_to_cull(
+    map: &MapDef,
+    from: RoomId,
+    settings: &CullSettings,
+) -> Result<Vec<RoomId>, MapError> {
+    let origin = map.room(from)?.position;
+    let max_world_distance_squared = settings.max_world_distance.map(|d| d * d);
+    let is_kept = |id: RoomId, graph_distance: u32| {
+        if id == from {
+            return true;
+        }
+        if matches!(settings.max_graph_distance, Some(max) if graph_distance > max) {
+            return false;
+        }
+        match (max_world_distance_squared, map.room(id)) {
+            (Some(max), Ok(room)) => distance_squared(&origin, &room.position) <= max,
+            (None, Ok(_)) => true,
+            (_, Err(_)) => false,
+        }
+    };
+
+    let mut distances: HashMap<RoomId, u32> = HashMap::default();
+    distances.insert(from, 0);
+    let mut queue = VecDeque::new();
+    queue.push_back(from);
+    while let Some(id) = queue.pop_front() {
+        let distance = distances[&id] + 1;
+        for connection in map.neighbours(id)? {
+            if distances.contains_key(connection) || !is_kept(*connection, distance) {
+                continue;
+            }
+            distances.insert(*connection, distance);
+            queue.push_back(*connection);
+        }
+    }
+    let kept: HashSet<RoomId> = distances.into_keys().collect();
+    let mut culled: Vec<RoomId> = map
+        .rooms()
+        .map(|(id, _)| *id)
+        .filter(|id| !kept.contains(id))
+        .collect();
+    culled.sort_by_key(|id| id.0);
+    Ok(culled)
+}
+
+/// Removes the rooms returned by [`rooms_to_cull`], and returns them.
+pub fn cull(
+    map: &mut MapDef,
+    from: RoomId,
+    settings: &CullSettings,
+) -> Result<Vec<RoomId>, MapError> {
+    let culled = rooms_to_cull(map, from, settings)?;
+    for id in culled.iter() {
+        map.remove_room(*id)?;
+    }
+    Ok(culled)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::map::{Room, RoomType};
+
+    /// Rooms 0 to 4 in a line, 10 units apart, and room 5 far away but connected to room 1.
+    fn line_map() -> MapDef {
+        let mut map = MapDef::default();
+        for i in 0..5 {
+            map.add_room(
+                RoomId(i),
+                Room::new((i as f32 * 10f32, 0f32), RoomType::safe()),
+            )
+            .unwrap();
+            if i > 0 {
+                map.connect(RoomId(i - 1), RoomId(i)).unwrap();
+            }
+        }
+        map.add_room(RoomId(5), Room::new((0f32, 500f32), RoomType::safe()))
+            .unwrap();
+        map.connect(RoomId(1), RoomId(5)).unwrap();
+        map
+    }
+
+    #[test]
+    fn cull_by_graph_distance() {
+        let mut map = line_map();
+        let settings = CullSettings {
+            max_graph_distance: Some(2),
+            max_world_distance: None,
+        };
+        let culled = cull(&mut map, RoomId(0), &settings).unwrap();
+        assert_eq!(culled, vec![RoomId(3), RoomId(4)]);
+        assert_eq!(map.len(), 4);
+        assert_eq!(map.validate(), Ok(()));
+    }
+    #[test]
+    fn cull_by_world_distance_keeps_map_connected() {
+        let mut map = line_map();
+        // Room 6 is close enough, but can only be reached through room 5 which is too far.
+        map.add_room(RoomId(6), Room::new((10f32, 10f32), RoomType::safe()))
+            .unwrap();
+        map.connect(RoomId(5), RoomId(6)).unwrap();
+        let settings = CullSettings {
+            max_graph_distance: None,
+            max_world_distance: Some(25f32),
+        };
+        let culled = rooms_to_cull(&map, RoomId(1), &settings).unwrap();
+        assert_eq!(culled, vec![RoomId(4), RoomId(5), RoomId(6)]);
+    }
+    #[test]
+    fn disabled_settings_cull_nothing() {
+        let map = line_map();
+        let settings = CullSettings::default();
+        assert!(!settings.is_enabled());
+        assert!(rooms_to_cull(&map, RoomId(4), &settings)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    map::{MapDef, MapError, RoomId},
+    pathfinding::Path,
+    poisson::distance_squared,
+};
+
+/// A growing danger zone, its radius grows by `radius_increase_per_second` times the
+/// multiplier of [`DangerGrowth`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct DangerZoneState {
+    pub center: (f32, f32),
+    pub radius: f32,
+    pub radius_increase_per_second: f32,
+}
+
+/// Multiplier shared by every danger zone, itself increasing linearly over time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DangerGrowth {
+    pub multiplier: f32,
+    pub multiplier_gain_per_second: f32,
+}
+
+impl DangerGrowth {
+    /// How much the radius of a zone growing at `speed` increases in `seconds`.
+    pub fn grown_distance(&self, speed: f32, seconds: f32) -> f32 {
+        speed
+            * (self.multiplier * seconds
+                + self.multiplier_gain_per_second * seconds * seconds / 2f32)
+    }
+}
+
+/// Seconds until `zone` reaches `point`, `None` if it never does.
+pub fn time_to_reach(
+    zone: &DangerZoneState,
+    growth: &DangerGrowth,
+    point: &(f32, f32),
+) -> Option<f32> {
+    let gap = distance_squared(&zone.center, point).sqrt() - zone.radius;
+    if gap <= 0f32 {
+        return Some(0f32);
+    }
+    // Solves a * t² + b * t = gap, from `DangerGrowth::grown_distance`.
+    let a = zone.radius_increase_per_second * growth.multiplier_gain_per_second / 2f32;
+    let b = zone.radius_increase_per_second * growth.multiplier;
+    if a == 0f32 {
+        return if b > 0f32 { Some(gap / b) } else { None };
+    }
+    let discriminant = b * b + 4f32 * a * gap;
+    if discriminant < 0f32 {
+        return None;
+    }
+    let t = (-b + discriminant.sqrt()) / (2f32 * a);
+    if t >= 0f32 {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+/// Seconds until the first danger zone reaches each room, rooms never reached are missing.
+pub fn engulf_times(
+    map: &MapDef,
+    zones: &[DangerZoneState],
+    growth: &DangerGrowth,
+) -> HashMap<RoomId, f32> {
+    let mut times = HashMap::default();
+    for (id, room) in map.rooms() {
+        let first = zones
+            .iter()
+            .filter_map(|zone| time_to_reach(zone, growth, &room.position))
+            .fold(None, |first: Option<f32>, t| {
+                Some(first.map_or(t, |f| f.min(t)))
+            });
+        if let Some(t) = first {
+            times.insert(*id, t);
+        }
+    }
+    times
+}
+
+/// True if every room of `rooms` is reached before the danger, when leaving the first one
+/// now and spending `hop_duration` seconds per move.
+pub fn is_route_safe(
+    rooms: &[RoomId],
+    engulf_times: &HashMap<RoomId, f32>,
+    hop_duration: f32,
+) -> bool {
+    rooms
+        .iter()
+        .enumerate()
+        .skip(1)
+        .all(|(hops, id)| match engulf_times.get(id) {
+            Some(t) => hops as f32 * hop_duration < *t,
+            None => true,
+        })
+}
+
+/// Route from `from` to `to` with the fewest moves among those staying ahead of the danger,
+/// see [`is_route_safe`]. The cost of the returned [`Path`] is its duration in seconds.
+pub fn safe_route(
+    map: &MapDef,
+    from: RoomId,
+    to: RoomId,
+    engulf_times: &HashMap<RoomId, f32>,
+    hop_duration: f32,
+) -> Result<Option<Path>, MapError> {
+    map.room(from)?;
+    map.room(to)?;
+    // Every move takes the same time, so a breadth-first search gives the earliest arrivals.
+    let mut previous: HashMap<RoomId, RoomId> = HashMap::default();
+    let mut hops: HashMap<RoomId, u32> = HashMap::default();
+    hops.insert(from, 0);
+    let mut queue = VecDeque::new();
+    queue.push_back(from);
+    while let Some(id) = queue.pop_front() {
+        if id == to {
+            let mut rooms = vec![to];
+            while let Some(p) = previous.get(rooms.last().unwrap()) {
+                rooms.push(*p);
+            }
+            rooms.reverse();
+            return Ok(Some(Path {
+                cost: hops[&id] as f32 * hop_duration,
+                rooms,
+            }));
+        }
+        let next_hops = hops[&id] + 1;
+        let arrival = next_hops as f32 * hop_duration;
+        for connection in map.neighbours(id)? {
+            if hops.contains_key(connection) {
+                continue;
+            }
+            if matches!(engulf_times.get(connection), Some(t) if arrival >= *t) {
+                continue;
+            }
+            hops.insert(*connection, next_hops);
+            previous.insert(*connection, id);
+            queue.push_back(*connection);
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::map::{Room, RoomType};
+
+    fn growth(multiplier_gain_per_second: f32) -> DangerGrowth {
+        DangerGrowth {
+            multiplier: 1f32,
+            multiplier_gain_per_second,
+        }
+    }
+
+    #[test]
+    fn time_to_reach_matches_growth() {
+        let zone = DangerZoneState {
+            center: (0f32, 0f32),
+            radius: 2f32,
+            radius_increase_per_second: 4f32,
+        };
+        assert_eq!(
+            time_to_reach(&zone, &growth(0f32), &(1f32, 0f32)),
+            Some(0f32)
+        );
+        assert_eq!(
+            time_to_reach(&zone, &growth(0f32), &(10f32, 0f32)),
+            Some(2f32)
+        );
+        let accelerating = growth(0.5f32);
+        let t = time_to_reach(&zone, &accelerating, &(30f32, 0f32)).unwrap();
+        let radius = zone.radius + accelerating.grown_distance(zone.radius_increase_per_second, t);
+        assert!((radius - 30f32).abs() < 0.001f32);
+        let still = DangerZoneState {
+            radius_increase_per_second: 0f32,
+            ..zone
+        };
+        assert_eq!(time_to_reach(&still, &accelerating, &(30f32, 0f32)), None);
+    }
+    #[test]
+    fn safe_route_goes_around_the_danger() {
+        // 0 -- 1 -- 2, and 0 -- 3 -- 4 -- 2: room 1 is about to be engulfed.
+        let mut map = MapDef::default();
+        let positions = [
+            (0f32, 0f32),
+            (10f32, 0f32),
+            (20f32, 0f32),
+            (0f32, 10f32),
+            (20f32, 10f32),
+        ];
+        for (i, position) in positions.iter().enumerate() {
+            map.add_room(RoomId(i), Room::new(*position, RoomType::safe()))
+                .unwrap();
+        }
+        for (a, b) in [(0, 1), (1, 2), (0, 3), (3, 4), (4, 2)].iter() {
+            map.connect(RoomId(*a), RoomId(*b)).unwrap();
+        }
+        let zones = [DangerZoneState {
+            center: (10f32, -3f32),
+            radius: 1f32,
+            radius_increase_per_second: 2f32,
+        }];
+        let times = engulf_times(&map, &zones, &growth(0f32));
+        assert_eq!(times[&RoomId(1)], 1f32);
+        let route = safe_route(&map, RoomId(0), RoomId(2), &times, 1f32)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            route.rooms,
+            vec![RoomId(0), RoomId(3), RoomId(4), RoomId(2)]
+        );
+        assert_eq!(route.cost, 3f32);
+        assert!(is_route_safe(&route.rooms, &times, 1f32));
+        assert!(!is_route_safe(
+            &[RoomId(0), RoomId(1), RoomId(2)],
+            &times,
+            1f32
+        ));
+        // Fast enough to go through room 1.
+        let fast = safe_route(&map, RoomId(0), RoomId(2), &times, 0.4f32)
+            .unwrap()
+            .unwrap();
+        assert_eq!(fast.rooms, vec![RoomId(0), RoomId(1), RoomId(2)]);
+    }
+}
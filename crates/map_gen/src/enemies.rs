@@ -0,0 +1,198 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    generator::MIN_DISTANCE_BETWEEN_ROOMS,
+    map::{MapDef, RoomId},
+    pathfinding::{shortest_path, FewestMoves},
+    poisson::distance_squared,
+};
+
+/// Special behaviour of an enemy during a battle.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum EnemyAbility {
+    /// Fraction of the player attack blocked, between 0 and 1 excluded.
+    Armor(f32),
+    /// Health regained after each blow the enemy survives, up to its maximum.
+    Regeneration(f32),
+    /// Hits before the player instead of after.
+    FirstStrike,
+}
+
+/// An enemy, as declared by designers, before scaling by [`DifficultyCurve`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EnemyTemplate {
+    pub name: String,
+    pub hp: f32,
+    pub attack: f32,
+    /// Coins won by defeating it.
+    pub reward: u32,
+    /// Closer than this difficulty level, the enemy is never picked.
+    #[serde(default)]
+    pub min_level: u32,
+    #[serde(default)]
+    pub ability: Option<EnemyAbility>,
+}
+
+/// Distance from the first room, `RoomId(0)`, counted by difficulty levels.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DifficultyMeasure {
+    /// Moves needed to reach the room, per level.
+    GraphDistance(f32),
+    /// World distance to the first room, per level.
+    WorldDistance(f32),
+}
+
+/// How much stronger and more rewarding enemies get far from the first room.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DifficultyCurve {
+    pub measure: DifficultyMeasure,
+    /// Fraction of the template hp added per level.
+    pub hp_per_level: f32,
+    /// Fraction of the template attack added per level.
+    pub attack_per_level: f32,
+    /// Coins added to the template reward per level.
+    pub reward_per_level: u32,
+}
+
+impl Default for DifficultyCurve {
+    fn default() -> Self {
+        Self {
+            measure: DifficultyMeasure::GraphDistance(3f32),
+            hp_per_level: 0.5f32,
+            attack_per_level: 0.25f32,
+            reward_per_level: 1,
+        }
+    }
+}
+
+/// An enemy scaled to the level of its room.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Enemy {
+    pub name: String,
+    pub hp: f32,
+    pub attack: f32,
+    pub reward: u32,
+    pub ability: Option<EnemyAbility>,
+}
+
+impl DifficultyCurve {
+    /// Difficulty level of `room`. When the first room was culled, the graph distance is
+    /// estimated from the world distance to where it was.
+    pub fn level(&self, map: &MapDef, room: RoomId) -> u32 {
+        let position = match map.room(room) {
+            Ok(room) => room.position,
+            Err(_) => return 0,
+        };
+        let world_distance = distance_squared(&position, &(0f32, 0f32)).sqrt();
+        let (distance, per_level) = match self.measure {
+            DifficultyMeasure::GraphDistance(per_level) => {
+                let moves = match shortest_path(map, room, RoomId(0), &FewestMoves) {
+                    Ok(Some(path)) => path.rooms.len().saturating_sub(1) as f32,
+                    _ => world_distance / MIN_DISTANCE_BETWEEN_ROOMS,
+                };
+                (moves, per_level)
+            }
+            DifficultyMeasure::WorldDistance(per_level) => (world_distance, per_level),
+        };
+        if per_level <= 0f32 {
+            return 0;
+        }
+        (distance / per_level) as u32
+    }
+
+    pub fn scale(&self, template: &EnemyTemplate, level: u32) -> Enemy {
+        let level_f = level as f32;
+        Enemy {
+            name: template.name.clone(),
+            hp: template.hp * (1f32 + self.hp_per_level * level_f),
+            attack: template.attack * (1f32 + self.attack_per_level * level_f),
+            reward: template.reward + self.reward_per_level * level,
+            ability: template.ability.clone(),
+        }
+    }
+}
+
+/// Picks one of the templates allowed at `level` and scales it, `None` without templates.
+/// When none is allowed yet, the template with the lowest `min_level` is used.
+pub fn pick_enemy(
+    templates: &[EnemyTemplate],
+    curve: &DifficultyCurve,
+    level: u32,
+    rng: &mut impl Rng,
+) -> Option<Enemy> {
+    let allowed: Vec<&EnemyTemplate> = templates.iter().filter(|t| t.min_level <= level).collect();
+    let template = match allowed.choose(rng) {
+        Some(template) => *template,
+        None => templates.iter().min_by_key(|t| t.min_level)?,
+    };
+    Some(curve.scale(template, level))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::map::{Room, RoomType};
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    fn template(name: &str, min_level: u32) -> EnemyTemplate {
+        EnemyTemplate {
+            name: name.to_string(),
+            hp: 2f32,
+            attack: 1f32,
+            reward: 1,
+            min_level,
+            ability: None,
+        }
+    }
+
+    #[test]
+    fn level_grows_with_distance() {
+        let mut map = MapDef::default();
+        for i in 0..4 {
+            map.add_room(
+                RoomId(i),
+                Room::new((i as f32 * 40f32, 0f32), RoomType::safe()),
+            )
+            .unwrap();
+            if i > 0 {
+                map.connect(RoomId(i - 1), RoomId(i)).unwrap();
+            }
+        }
+        let curve = DifficultyCurve {
+            measure: DifficultyMeasure::GraphDistance(2f32),
+            ..DifficultyCurve::default()
+        };
+        assert_eq!(curve.level(&map, RoomId(1)), 0);
+        assert_eq!(curve.level(&map, RoomId(3)), 1);
+        let curve = DifficultyCurve {
+            measure: DifficultyMeasure::WorldDistance(40f32),
+            ..DifficultyCurve::default()
+        };
+        assert_eq!(curve.level(&map, RoomId(3)), 3);
+        // Without the first room, the graph distance is estimated.
+        map.remove_room(RoomId(0)).unwrap();
+        let curve = DifficultyCurve {
+            measure: DifficultyMeasure::GraphDistance(1f32),
+            ..DifficultyCurve::default()
+        };
+        assert_eq!(curve.level(&map, RoomId(3)), 3);
+    }
+    #[test]
+    fn far_enemies_are_stronger() {
+        let templates = vec![template("Rat", 0), template("Troll", 2)];
+        let curve = DifficultyCurve::default();
+        let mut rng = ChaCha20Rng::seed_from_u64(0);
+        for _ in 0..10 {
+            let enemy = pick_enemy(&templates, &curve, 1, &mut rng).unwrap();
+            assert_eq!(enemy.name, "Rat");
+            assert_eq!(enemy.hp, 3f32);
+            assert_eq!(enemy.reward, 2);
+        }
+        let far = pick_enemy(&templates[1..], &curve, 0, &mut rng).unwrap();
+        assert_eq!(far.name, "Troll");
+        assert!(pick_enemy(&[], &curve, 0, &mut rng).is_none());
+    }
+}
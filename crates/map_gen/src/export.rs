@@ -0,0 +1,216 @@
+use std::fmt::Write;
+
+use crate::{
+    danger_forecast::DangerZoneState,
+    map::{MapDef, Room, RoomId},
+    room_definitions::RoomDefinitions,
+};
+
+const ROOM_RADIUS: f32 = 8f32;
+const MARGIN: f32 = 20f32;
+/// Rooms whose type is not in the definitions.
+const UNKNOWN_COLOR: [f32; 3] = [0.5f32, 0.5f32, 0.5f32];
+
+/// Game state drawn along the map by [`to_svg`] and [`to_dot`].
+#[derive(Clone, Debug, Default)]
+pub struct ExportState {
+    pub player: Option<RoomId>,
+    pub danger_zones: Vec<DangerZoneState>,
+}
+
+/// Rooms sorted by id, so exports of the same map are identical.
+fn sorted_rooms(map: &MapDef) -> Vec<(RoomId, &Room)> {
+    let mut rooms: Vec<(RoomId, &Room)> = map.rooms().map(|(id, room)| (*id, room)).collect();
+    rooms.sort_by_key(|(id, _)| id.0);
+    rooms
+}
+
+/// Each connection once, from the lowest id to the highest.
+fn sorted_links(map: &MapDef) -> Vec<(RoomId, RoomId)> {
+    let mut links = vec![];
+    for (id, room) in sorted_rooms(map) {
+        let mut connections: Vec<RoomId> = room
+            .connections()
+            .iter()
+            .filter(|c| c.0 > id.0)
+            .copied()
+            .collect();
+        connections.sort_by_key(|c| c.0);
+        links.extend(connections.into_iter().map(|c| (id, c)));
+    }
+    links
+}
+
+fn room_color(definitions: &RoomDefinitions, room: &Room) -> String {
+    let [r, g, b] = definitions
+        .get(&room.room_type)
+        .map_or(UNKNOWN_COLOR, |d| d.color);
+    let channel = |c: f32| (c.clamp(0f32, 1f32) * 255f32).round() as u8;
+    format!("#{:02x}{:02x}{:02x}", channel(r), channel(g), channel(b))
+}
+
+/// SVG image of the map, y pointing up as in the game.
+pub fn to_svg(map: &MapDef, definitions: &RoomDefinitions, state: &ExportState) -> String {
+    let rooms = sorted_rooms(map);
+    let mut min = (0f32, 0f32);
+    let mut max = (0f32, 0f32);
+    let mut extend = |(x, y): (f32, f32), radius: f32| {
+        min = (min.0.min(x - radius), min.1.min(y - radius));
+        max = (max.0.max(x + radius), max.1.max(y + radius));
+    };
+    for (_, room) in rooms.iter() {
+        extend(room.position, ROOM_RADIUS);
+    }
+    for zone in state.danger_zones.iter() {
+        extend(zone.center, zone.radius);
+    }
+
+    let mut svg = String::new();
+    // Writing to a String can't fail.
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}">"#,
+        min.0 - MARGIN,
+        -max.1 - MARGIN,
+        max.0 - min.0 + 2f32 * MARGIN,
+        max.1 - min.1 + 2f32 * MARGIN
+    );
+    for zone in state.danger_zones.iter() {
+        let _ = writeln!(
+            svg,
+            r#"  <circle cx="{}" cy="{}" r="{}" fill="red" fill-opacity="0.3"/>"#,
+            zone.center.0, -zone.center.1, zone.radius
+        );
+    }
+    for (a, b) in sorted_links(map) {
+        if let (Ok(from), Ok(to)) = (map.room(a), map.room(b)) {
+            let _ = writeln!(
+                svg,
+                r#"  <line x1="{}" y1="{}" x2="{}" y2="{}" stroke="darkcyan" stroke-width="2"/>"#,
+                from.position.0, -from.position.1, to.position.0, -to.position.1
+            );
+        }
+    }
+    for (id, room) in rooms.iter() {
+        let opacity = if room.visited { 0.4f32 } else { 1f32 };
+        let _ = writeln!(
+            svg,
+            r#"  <circle cx="{}" cy="{}" r="{}" fill="{}" fill-opacity="{}" stroke="black"><title>{} {}</title></circle>"#,
+            room.position.0,
+            -room.position.1,
+            ROOM_RADIUS,
+            room_color(definitions, room),
+            opacity,
+            id.0,
+            room.room_type.0
+        );
+    }
+    if let Some(player) = state.player.and_then(|id| map.room(id).ok()) {
+        let _ = writeln!(
+            svg,
+            r#"  <circle cx="{}" cy="{}" r="{}" fill="none" stroke="blue" stroke-width="3"/>"#,
+            player.position.0,
+            -player.position.1,
+            ROOM_RADIUS * 1.5f32
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Graphviz graph of the map, positions are kept when laid out with `neato -n`.
+pub fn to_dot(map: &MapDef, definitions: &RoomDefinitions, state: &ExportState) -> String {
+    let mut dot = String::new();
+    dot.push_str("graph map {\n");
+    dot.push_str("  node [shape=circle, style=filled, fixedsize=true, width=0.4];\n");
+    for (id, room) in sorted_rooms(map) {
+        let mut attributes = vec![
+            format!("label=\"{}\\n{}\"", id.0, room.room_type.0),
+            format!("pos=\"{},{}!\"", room.position.0, room.position.1),
+            format!("fillcolor=\"{}\"", room_color(definitions, room)),
+        ];
+        if room.visited {
+            attributes.push("penwidth=3".to_string());
+        }
+        if state.player == Some(id) {
+            attributes.push("shape=doublecircle, color=blue".to_string());
+        }
+        let _ = writeln!(dot, "  r{} [{}];", id.0, attributes.join(", "));
+    }
+    for (i, zone) in state.danger_zones.iter().enumerate() {
+        // Width is in inches, positions in points.
+        let _ = writeln!(
+            dot,
+            "  danger{} [label=\"\", pos=\"{},{}!\", width={}, fillcolor=\"#ff00004c\", color=red];",
+            i,
+            zone.center.0,
+            zone.center.1,
+            zone.radius * 2f32 / 72f32
+        );
+    }
+    for (a, b) in sorted_links(map) {
+        let _ = writeln!(dot, "  r{} -- r{};", a.0, b.0);
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::map::RoomType;
+
+    fn state_and_map() -> (ExportState, MapDef) {
+        let mut map = MapDef::default();
+        map.add_room(RoomId(0), Room::new((0f32, 0f32), RoomType::safe()))
+            .unwrap();
+        map.add_room(RoomId(1), Room::new((40f32, 10f32), RoomType::new("Coins")))
+            .unwrap();
+        map.add_room(
+            RoomId(2),
+            Room::new((0f32, 40f32), RoomType::new("Unknown")),
+        )
+        .unwrap();
+        map.connect(RoomId(0), RoomId(1)).unwrap();
+        map.connect(RoomId(2), RoomId(0)).unwrap();
+        map.room_mut(RoomId(0)).unwrap().visited = true;
+        let state = ExportState {
+            player: Some(RoomId(0)),
+            danger_zones: vec![DangerZoneState {
+                center: (-30f32, 0f32),
+                radius: 10f32,
+                radius_increase_per_second: 1f32,
+            }],
+        };
+        (state, map)
+    }
+
+    #[test]
+    fn svg_draws_every_room_and_link_once() {
+        let (state, map) = state_and_map();
+        let svg = to_svg(&map, &RoomDefinitions::default(), &state);
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<line").count(), 2);
+        // Rooms, the danger zone and the player.
+        assert_eq!(svg.matches("<circle").count(), 5);
+        assert!(svg.contains(r##"fill="#00ff00""##));
+        assert!(svg.contains(r##"fill="#808080""##));
+        assert_eq!(
+            svg,
+            to_svg(&map, &RoomDefinitions::default(), &state),
+            "exports are deterministic"
+        );
+    }
+    #[test]
+    fn dot_lists_rooms_and_links() {
+        let (state, map) = state_and_map();
+        let dot = to_dot(&map, &RoomDefinitions::default(), &state);
+        assert!(dot.contains("  r0 -- r1;\n"));
+        assert!(dot.contains("  r0 -- r2;\n"));
+        assert_eq!(dot.matches(" -- ").count(), 2);
+        assert!(dot.contains("r0 [label=\"0\\nSafe\", pos=\"0,0!\""));
+        assert!(dot.contains("penwidth=3, shape=doublecircle"));
+        assert!(dot.contains("danger0"));
+    }
+}
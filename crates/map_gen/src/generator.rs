@@ -0,0 +1,723 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use rand::prelude::Distribution;
+use rand::Rng;
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    biome::Biomes,
+    connection::{ClosestRoom, ConnectionStrategy},
+    corridors::BranchingCorridors,
+    geometry::segments_cross,
+    hex_lattice::HexLattice,
+    hub_and_spoke::HubAndSpoke,
+    map::{MapDef, MapError, Room, RoomId, RoomType},
+    poisson::{distance_squared, sample_near, Poisson},
+    random::{RandomDeterministic, RandomStream},
+    room_chances::RoomChanceWeights,
+};
+
+/// Bumped whenever the maps generated for a seed and a configuration change.
+///
+/// Generators keep generating the previous maps for older versions, see
+/// [`RoomFactory::version`], and each version has its golden maps in `tests/`.
+///
+/// - 2: with planar links, [`RingExpansion`] draws another position when the link to the
+///   room it expands from would cross, rather than creating one room less.
+/// - 3: links skipped because of the loop density no longer count towards
+///   [`ConnectionStrategy::max_links`], other candidates are tried instead.
+pub const GENERATOR_VERSION: u32 = 3;
+/// Oldest [`GENERATOR_VERSION`] still reproduced.
+pub const OLDEST_GENERATOR_VERSION: u32 = 1;
+
+pub fn is_supported_version(version: u32) -> bool {
+    (OLDEST_GENERATOR_VERSION..=GENERATOR_VERSION).contains(&version)
+}
+
+/// A generator version this build can't reproduce, see [`is_supported_version`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnsupportedVersion(pub u32);
+
+impl fmt::Display for UnsupportedVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unsupported generator version {} (supported from {} to {})",
+            self.0, OLDEST_GENERATOR_VERSION, GENERATOR_VERSION
+        )
+    }
+}
+
+impl std::error::Error for UnsupportedVersion {}
+
+pub const MIN_DISTANCE_BETWEEN_ROOMS: f32 = 40f32;
+const INITIAL_ROOMS: usize = 2;
+/// Rooms further than this from a new room are never linked to it.
+const MAX_LINK_DISTANCE: f32 = MIN_DISTANCE_BETWEEN_ROOMS * 1.5f32;
+
+/// A room added to the map by [`MapGenerator::expand`].
+///
+/// `links` are the connections made when the room was created, each one should be drawn once.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoomCreated {
+    pub id: RoomId,
+    pub battle: bool,
+    pub links: Vec<RoomId>,
+}
+
+/// Creates and extends a [`MapDef`], without any dependency on a game engine.
+///
+/// Generators only decide where rooms go, see [`Layout`] for the available ones.
+/// Their randomness comes from the [`RoomFactory`] they are created with.
+pub trait MapGenerator {
+    /// Creates the starting map, the player starts in room 0.
+    fn create_map(&mut self) -> MapDef;
+
+    /// Adds new rooms around `from_room_id`, returns them in creation order.
+    fn expand(
+        &mut self,
+        map: &mut MapDef,
+        from_room_id: RoomId,
+    ) -> Result<Vec<RoomCreated>, MapError>;
+}
+
+/// Shared by every [`MapGenerator`]: picks the type of new rooms and links them.
+pub struct RoomFactory<'a> {
+    random: &'a mut RandomDeterministic,
+    chances: &'a RoomChanceWeights,
+    biomes: Option<&'a Biomes>,
+    planar: bool,
+    connections: &'a dyn ConnectionStrategy,
+    loop_density: f32,
+    version: u32,
+}
+
+impl<'a> RoomFactory<'a> {
+    pub fn new(random: &'a mut RandomDeterministic, chances: &'a RoomChanceWeights) -> Self {
+        Self {
+            random,
+            chances,
+            biomes: None,
+            planar: false,
+            connections: &ClosestRoom,
+            loop_density: 1f32,
+            version: GENERATOR_VERSION,
+        }
+    }
+
+    /// Generates the maps of an older [`GENERATOR_VERSION`], fails if it is not supported
+    /// according to [`is_supported_version`]. The default is the latest version.
+    pub fn with_version(mut self, version: u32) -> Result<Self, UnsupportedVersion> {
+        if !is_supported_version(version) {
+            return Err(UnsupportedVersion(version));
+        }
+        self.version = version;
+        Ok(self)
+    }
+
+    /// Version of the maps to generate: a change to generation applies only from the version
+    /// introducing it, so runs recorded with older versions are generated the same.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// New rooms pick their type from the biome at their position rather than from the
+    /// chances given to [`RoomFactory::new`], which still set how many rooms are attempted.
+    pub fn with_biomes(mut self, biomes: &'a Biomes) -> Self {
+        self.biomes = Some(biomes);
+        self
+    }
+
+    /// When `planar` is set, links crossing existing ones are never created: a new room
+    /// whose link to the room it expands from would cross is not created, and its link to
+    /// the closest room goes to the next closest room instead.
+    pub fn with_planar_links(mut self, planar: bool) -> Self {
+        self.planar = planar;
+        self
+    }
+
+    /// Besides the room it expands from, a new room links to the candidates of `connections`,
+    /// each with a `loop_density` chance: 0 makes the map a tree.
+    /// The default links to the closest room.
+    pub fn with_connections(
+        mut self,
+        connections: &'a dyn ConnectionStrategy,
+        loop_density: f32,
+    ) -> Self {
+        self.connections = connections;
+        self.loop_density = loop_density;
+        self
+    }
+
+    pub(crate) fn rng(&mut self) -> &mut ChaCha20Rng {
+        self.random.stream(RandomStream::Layout)
+    }
+
+    pub(crate) fn rooms_to_create_on_move(&self) -> u32 {
+        self.chances.rooms_to_create_on_move
+    }
+
+    /// False if a new room at `position` can't be linked to `from` without crossing a link.
+    pub(crate) fn can_link(&self, map: &MapDef, from: RoomId, position: &(f32, f32)) -> bool {
+        !(self.planar && crosses_links(map, from, position))
+    }
+
+    /// Type of a new room at `position` and whether it holds a battle, `None` when
+    /// `duplicates` already holds as many rooms of the picked type as allowed.
+    pub(crate) fn pick_room(
+        &mut self,
+        position: &(f32, f32),
+        duplicates: &mut HashMap<RoomType, u32>,
+    ) -> Option<(RoomType, bool)> {
+        let chances = self
+            .biomes
+            .map_or(self.chances, |b| &b.biome_at(position).chances);
+        let type_index = chances
+            .weighted_index
+            .sample(self.random.stream(RandomStream::RoomTypes));
+        let definition = &chances.definitions[type_index];
+        let counter = duplicates
+            .entry(definition.type_room.clone())
+            .or_insert(definition.max_rooms_create);
+        if *counter == 0 {
+            return None;
+        }
+        *counter -= 1;
+        let battle =
+            self.random.stream(RandomStream::Combat).gen::<f64>() < definition.battle_chance;
+        Some((definition.type_room.clone(), battle))
+    }
+
+    /// Adds a room linked to `from`, and to the rooms closer than `max_link_distance`
+    /// chosen by the connection strategy.
+    pub(crate) fn add_room(
+        &mut self,
+        map: &mut MapDef,
+        from: RoomId,
+        position: (f32, f32),
+        (room_type, battle): (RoomType, bool),
+        max_link_distance: f32,
+    ) -> Result<RoomCreated, MapError> {
+        let id = map.allocate_id();
+        map.add_room(id, Room::new(position, room_type))?;
+        map.connect(from, id)?;
+
+        let connections = self.connections;
+        let mut links = 0;
+        for candidate in connections.candidates(map, from, id, max_link_distance) {
+            if matches!(connections.max_links(), Some(max) if links >= max) {
+                break;
+            }
+            if !self.can_link(map, candidate, &position) {
+                continue;
+            }
+            // Up to version 2, links skipped by the density draw were counted too.
+            if self.version < 3 {
+                links += 1;
+            }
+            // No draw at full density, so maps generated before it existed stay the same.
+            if self.loop_density < 1f32 && self.rng().gen::<f32>() >= self.loop_density {
+                continue;
+            }
+            if self.version >= 3 {
+                links += 1;
+            }
+            map.connect(candidate, id)?;
+        }
+        Ok(RoomCreated {
+            id,
+            battle,
+            links: map.neighbours(id)?.to_vec(),
+        })
+    }
+}
+
+/// True if no room of `map` is closer than [`MIN_DISTANCE_BETWEEN_ROOMS`] to `position`.
+pub(crate) fn is_free(map: &MapDef, position: &(f32, f32)) -> bool {
+    map.spatial()
+        .within_radius(position, MIN_DISTANCE_BETWEEN_ROOMS)
+        .is_empty()
+}
+
+/// The available [`MapGenerator`]s, serializable for [`crate::configuration::MapConfiguration`].
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Layout {
+    RingExpansion,
+    HexLattice,
+    HubAndSpoke,
+    BranchingCorridors,
+}
+
+impl Layout {
+    pub fn generator<'a>(&self, factory: RoomFactory<'a>) -> Box<dyn MapGenerator + 'a> {
+        match self {
+            Layout::RingExpansion => Box::new(RingExpansion::new(factory)),
+            Layout::HexLattice => Box::new(HexLattice::new(factory)),
+            Layout::HubAndSpoke => Box::new(HubAndSpoke::new(factory)),
+            Layout::BranchingCorridors => Box::new(BranchingCorridors::new(factory)),
+        }
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout::RingExpansion
+    }
+}
+
+/// Rooms scattered at random around the room expanded from, with Poisson-disk sampling.
+pub struct RingExpansion<'a> {
+    factory: RoomFactory<'a>,
+}
+
+impl<'a> RingExpansion<'a> {
+    pub fn new(factory: RoomFactory<'a>) -> Self {
+        Self { factory }
+    }
+}
+
+impl<'a> MapGenerator for RingExpansion<'a> {
+    /// A safe room at the origin and its first neighbours.
+    fn create_map(&mut self) -> MapDef {
+        let mut positions = vec![(0f32, 0f32)];
+        let mut poisson = Poisson::from_points(&positions, MIN_DISTANCE_BETWEEN_ROOMS);
+        let mut root_index = RoomId(0);
+        let mut new_map = MapDef::default();
+        new_map
+            .add_room(root_index, Room::new(positions[0], RoomType::safe()))
+            .expect("the map is empty");
+
+        let rng = self.factory.rng();
+        let mut room_id_to_create = RoomId(1);
+        while root_index.0 < positions.len() && new_map.len() < INITIAL_ROOMS {
+            let ref_point = positions[root_index.0];
+
+            if let Some(new_position) = poisson.compute_new_position(&ref_point, 5, &mut *rng) {
+                new_map
+                    .add_room(room_id_to_create, Room::new(new_position, RoomType::safe()))
+                    .expect("room ids are created in order");
+                new_map
+                    .connect(root_index, room_id_to_create)
+                    .expect("both rooms exist");
+                poisson.insert(new_position);
+                positions.push(new_position);
+                room_id_to_create.0 += 1;
+            } else {
+                root_index.0 += 1;
+            }
+        }
+        new_map
+    }
+
+    fn expand(
+        &mut self,
+        map: &mut MapDef,
+        from_room_id: RoomId,
+    ) -> Result<Vec<RoomCreated>, MapError> {
+        let ref_point = map.room(from_room_id)?.position;
+        let mut created = vec![];
+
+        let mut duplicates = HashMap::default();
+
+        for _ in 0..self.factory.rooms_to_create_on_move() {
+            let new_position = if self.factory.version() >= 2 {
+                let factory = &mut self.factory;
+                (0..10).find_map(|_| {
+                    sample_near(
+                        &ref_point,
+                        MIN_DISTANCE_BETWEEN_ROOMS,
+                        1,
+                        factory.rng(),
+                        |p| is_free(map, p),
+                    )
+                    .filter(|p| factory.can_link(map, from_room_id, p))
+                })
+            } else {
+                sample_near(
+                    &ref_point,
+                    MIN_DISTANCE_BETWEEN_ROOMS,
+                    10,
+                    self.factory.rng(),
+                    |p| is_free(map, p),
+                )
+                .filter(|p| self.factory.can_link(map, from_room_id, p))
+            };
+            let new_position = match new_position {
+                Some(p) => p,
+                None => continue,
+            };
+            let room = match self.factory.pick_room(&new_position, &mut duplicates) {
+                Some(room) => room,
+                None => continue,
+            };
+            created.push(self.factory.add_room(
+                map,
+                from_room_id,
+                new_position,
+                room,
+                MAX_LINK_DISTANCE,
+            )?);
+        }
+        Ok(created)
+    }
+}
+
+/// True if a link from room `from` to `to` would cross a link of `map`.
+fn crosses_links(map: &MapDef, from: RoomId, to: &(f32, f32)) -> bool {
+    let from_position = match map.room(from) {
+        Ok(room) => room.position,
+        Err(_) => return false,
+    };
+    // A crossing link has an end within half its length of the crossing point, itself within
+    // half the new link of its middle.
+    let middle = (
+        (from_position.0 + to.0) / 2f32,
+        (from_position.1 + to.1) / 2f32,
+    );
+    let radius = distance_squared(&from_position, to).sqrt() / 2f32 + map.longest_link();
+    map.spatial()
+        .within_radius(&middle, radius)
+        .into_iter()
+        .any(|(a, _)| {
+            let room_a = match map.room(a) {
+                Ok(room) if a != from => room,
+                _ => return false,
+            };
+            room_a.connections().iter().any(|b| {
+                // Links ending in `from` only touch the new one.
+                *b != from
+                    && matches!(map.room(*b), Ok(room_b)
+                        if segments_cross(&from_position, to, &room_a.position, &room_b.position))
+            })
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn generate(seed: u64, expansions: usize) -> MapDef {
+        let mut random = RandomDeterministic::new(seed);
+        let chances = RoomChanceWeights::default();
+        let mut generator = RingExpansion::new(RoomFactory::new(&mut random, &chances));
+        let mut map = generator.create_map();
+        for i in 0..expansions {
+            generator.expand(&mut map, RoomId(i)).unwrap();
+        }
+        map
+    }
+
+    #[test]
+    fn create_map_has_connected_start() {
+        let map = generate(1, 0);
+        assert_eq!(map.len(), INITIAL_ROOMS);
+        assert_eq!(map.neighbours(RoomId(0)).unwrap(), &[RoomId(1)]);
+        assert_eq!(map.neighbours(RoomId(1)).unwrap(), &[RoomId(0)]);
+    }
+    #[test]
+    fn same_seed_same_map() {
+        let map_a = generate(42, 5);
+        let map_b = generate(42, 5);
+        assert_eq!(map_a.len(), map_b.len());
+        for (id, room) in map_a.rooms() {
+            let other = map_b.room(*id).unwrap();
+            assert_eq!(room.position, other.position);
+            assert_eq!(room.room_type, other.room_type);
+            assert_eq!(room.connections(), other.connections());
+        }
+    }
+    #[test]
+    fn rooms_follow_their_biome() {
+        let mut random = RandomDeterministic::new(3);
+        let chances = RoomChanceWeights::default();
+        let mut biomes = Biomes::default();
+        // Only coins rooms in the first biome, only danger rooms in the others.
+        for (i, biome) in biomes.biomes.iter_mut().enumerate() {
+            let only = RoomType::new(if i == 0 { "Coins" } else { "Danger" });
+            let weights = biome
+                .chances
+                .definitions
+                .iter()
+                .map(|d| if d.type_room == only { 1 } else { 0 })
+                .collect();
+            for definition in biome.chances.definitions.iter_mut() {
+                definition.max_rooms_create = 10;
+            }
+            biome.chances.set_weights(weights).unwrap();
+        }
+        let mut generator =
+            RingExpansion::new(RoomFactory::new(&mut random, &chances).with_biomes(&biomes));
+        let mut map = generator.create_map();
+        for i in 0..10 {
+            generator.expand(&mut map, RoomId(i)).unwrap();
+        }
+        for (id, room) in map.rooms().filter(|(id, _)| id.0 >= INITIAL_ROOMS) {
+            let expected = RoomType::new(if biomes.index_at(&room.position) == 0 {
+                "Coins"
+            } else {
+                "Danger"
+            });
+            assert_eq!(room.room_type, expected, "room {}", id.0);
+        }
+    }
+    #[test]
+    fn planar_links_never_cross() {
+        for seed in 0..30 {
+            let mut random = RandomDeterministic::new(seed);
+            let chances = RoomChanceWeights::default();
+            let mut generator =
+                RingExpansion::new(RoomFactory::new(&mut random, &chances).with_planar_links(true));
+            let mut map = generator.create_map();
+            for i in 0..15 {
+                generator.expand(&mut map, RoomId(i)).unwrap();
+            }
+            let mut links = vec![];
+            for (a, room) in map.rooms() {
+                for b in room.connections().iter().filter(|b| b.0 > a.0) {
+                    links.push((room.position, map.room(*b).unwrap().position));
+                }
+            }
+            for (i, (a1, a2)) in links.iter().enumerate() {
+                for (b1, b2) in links.iter().skip(i + 1) {
+                    assert!(
+                        !segments_cross(a1, a2, b1, b2),
+                        "seed {}: {:?} crosses {:?}",
+                        seed,
+                        (a1, a2),
+                        (b1, b2)
+                    );
+                }
+            }
+        }
+    }
+    #[test]
+    fn crosses_links_finds_every_crossing() {
+        let mut crossings = 0;
+        for seed in 0..10 {
+            let map = generate(seed, 15);
+            let ids: Vec<RoomId> = map.rooms().map(|(id, _)| *id).collect();
+            for from in ids.iter() {
+                let from_position = map.room(*from).unwrap().position;
+                for (_, room) in map.rooms() {
+                    let to = (room.position.0 * 0.8f32, room.position.1 * 1.1f32);
+                    let expected = map.rooms().any(|(a, room_a)| {
+                        a != from
+                            && room_a.connections().iter().any(|b| {
+                                b != from
+                                    && segments_cross(
+                                        &from_position,
+                                        &to,
+                                        &room_a.position,
+                                        &map.room(*b).unwrap().position,
+                                    )
+                            })
+                    });
+                    assert_eq!(
+                        crosses_links(&map, *from, &to),
+                        expected,
+                        "seed {}: from {} to {:?}",
+                        seed,
+                        from.0,
+                        to
+                    );
+                    crossings += expected as usize;
+                }
+            }
+        }
+        assert!(crossings > 0);
+    }
+    #[test]
+    fn unsupported_versions_are_refused() {
+        let mut random = RandomDeterministic::new(0);
+        let chances = RoomChanceWeights::default();
+        for version in [0, GENERATOR_VERSION + 1].iter() {
+            let factory = RoomFactory::new(&mut random, &chances).with_version(*version);
+            assert_eq!(factory.err(), Some(UnsupportedVersion(*version)));
+        }
+        assert!(RoomFactory::new(&mut random, &chances)
+            .with_version(OLDEST_GENERATOR_VERSION)
+            .is_ok());
+    }
+    #[test]
+    fn planar_links_keep_rooms_from_version_2() {
+        let generate_version = |seed: u64, version: u32| {
+            let mut random = RandomDeterministic::new(seed);
+            let chances = RoomChanceWeights::default();
+            let mut generator = RingExpansion::new(
+                RoomFactory::new(&mut random, &chances)
+                    .with_planar_links(true)
+                    .with_version(version)
+                    .unwrap(),
+            );
+            let mut map = generator.create_map();
+            for i in 0..15 {
+                generator.expand(&mut map, RoomId(i)).unwrap();
+            }
+            map.len()
+        };
+        let (mut rooms_v1, mut rooms_v2) = (0, 0);
+        for seed in 0..30 {
+            rooms_v1 += generate_version(seed, 1);
+            rooms_v2 += generate_version(seed, 2);
+        }
+        // Version 2 no longer gives up rooms whose link would cross.
+        assert!(rooms_v2 > rooms_v1, "{} <= {}", rooms_v2, rooms_v1);
+    }
+    #[test]
+    fn skipped_links_dont_count_from_version_3() {
+        use crate::connection::Connections;
+        let generate_version = |seed: u64, version: u32| {
+            let mut random = RandomDeterministic::new(seed);
+            let chances = RoomChanceWeights::default();
+            let strategy = Connections::KNearest(2).strategy();
+            let mut generator = RingExpansion::new(
+                RoomFactory::new(&mut random, &chances)
+                    .with_connections(strategy.as_ref(), 0.5f32)
+                    .with_version(version)
+                    .unwrap(),
+            );
+            let mut map = generator.create_map();
+            for i in 0..15 {
+                generator.expand(&mut map, RoomId(i)).unwrap();
+            }
+            let links: usize = map.rooms().map(|(_, room)| room.connections().len()).sum();
+            links as f32 / map.len() as f32
+        };
+        let (mut links_v2, mut links_v3) = (0f32, 0f32);
+        for seed in 0..30 {
+            links_v2 += generate_version(seed, 2);
+            links_v3 += generate_version(seed, 3);
+        }
+        // Version 3 tries the next candidates when a link is skipped.
+        assert!(links_v3 > links_v2, "{} <= {}", links_v3, links_v2);
+    }
+    #[test]
+    fn loop_density_sets_alternative_routes() {
+        use crate::{connection::Connections, stats::MapStats};
+        let generate_with = |connections: Connections, loop_density: f32| {
+            let mut random = RandomDeterministic::new(8);
+            let chances = RoomChanceWeights::default();
+            let strategy = connections.strategy();
+            let mut generator = RingExpansion::new(
+                RoomFactory::new(&mut random, &chances)
+                    .with_connections(strategy.as_ref(), loop_density),
+            );
+            let mut map = generator.create_map();
+            for i in 0..15 {
+                generator.expand(&mut map, RoomId(i)).unwrap();
+            }
+            assert_eq!(map.validate(), Ok(()));
+            MapStats::compute(&map).cycles
+        };
+        for connections in [
+            Connections::ClosestRoom,
+            Connections::KNearest(3),
+            Connections::RelativeNeighbourhood,
+            Connections::Gabriel,
+        ]
+        .iter()
+        {
+            assert_eq!(generate_with(*connections, 0f32), 0, "{:?}", connections);
+            assert!(generate_with(*connections, 1f32) > 0, "{:?}", connections);
+        }
+        assert!(
+            generate_with(Connections::KNearest(3), 1f32)
+                > generate_with(Connections::ClosestRoom, 1f32)
+        );
+    }
+    #[test]
+    fn every_layout_is_deterministic() {
+        let layouts = [
+            Layout::RingExpansion,
+            Layout::HexLattice,
+            Layout::HubAndSpoke,
+            Layout::BranchingCorridors,
+        ];
+        for layout in layouts.iter() {
+            let generate_layout = |seed: u64| {
+                let mut random = RandomDeterministic::new(seed);
+                let chances = RoomChanceWeights::default();
+                let mut generator = layout.generator(RoomFactory::new(&mut random, &chances));
+                let mut map = generator.create_map();
+                for i in 0..8 {
+                    if map.contains(RoomId(i)) {
+                        generator.expand(&mut map, RoomId(i)).unwrap();
+                    }
+                }
+                map
+            };
+            let map = generate_layout(11);
+            assert_eq!(map.validate(), Ok(()), "{:?}", layout);
+            assert!(map.len() > INITIAL_ROOMS, "{:?}", layout);
+            let other = generate_layout(11);
+            for (id, room) in map.rooms() {
+                let other = other.room(*id).unwrap();
+                assert_eq!(room.position, other.position);
+                assert_eq!(room.connections(), other.connections());
+            }
+        }
+    }
+    /// Maps of every layout for one seed, with biomes and planar links.
+    /// Files of older versions must never change. After bumping [`GENERATOR_VERSION`],
+    /// create the file of the new version with `UPDATE_GOLDEN_MAPS=1 cargo test`.
+    #[test]
+    fn golden_maps() {
+        for version in OLDEST_GENERATOR_VERSION..=GENERATOR_VERSION {
+            check_golden_maps(version);
+        }
+    }
+
+    fn check_golden_maps(version: u32) {
+        let path = format!(
+            "{}/tests/golden_maps_v{}.ron",
+            env!("CARGO_MANIFEST_DIR"),
+            version
+        );
+        let chances = RoomChanceWeights::default();
+        let biomes = Biomes::default();
+        let layouts = [
+            Layout::RingExpansion,
+            Layout::HexLattice,
+            Layout::HubAndSpoke,
+            Layout::BranchingCorridors,
+        ];
+        let maps: Vec<(Layout, MapDef)> = layouts
+            .iter()
+            .map(|layout| {
+                let mut random = RandomDeterministic::new(42);
+                let factory = RoomFactory::new(&mut random, &chances)
+                    .with_biomes(&biomes)
+                    .with_planar_links(true)
+                    .with_version(version)
+                    .unwrap();
+                let mut generator = layout.generator(factory);
+                let mut map = generator.create_map();
+                for i in 0..4 {
+                    if map.contains(RoomId(i)) {
+                        generator.expand(&mut map, RoomId(i)).unwrap();
+                    }
+                }
+                (*layout, map)
+            })
+            .collect();
+        let serialized =
+            ron::ser::to_string_pretty(&maps, ron::ser::PrettyConfig::default()).unwrap();
+        if version == GENERATOR_VERSION && std::env::var("UPDATE_GOLDEN_MAPS").is_ok() {
+            std::fs::write(&path, &serialized).unwrap();
+        }
+        let golden = std::fs::read_to_string(&path).unwrap();
+        assert!(
+            serialized == golden,
+            "generated maps differ from {}, regenerate it if the change is intended",
+            path
+        );
+    }
+    #[test]
+    fn expanded_map_is_valid() {
+        for seed in 0..20 {
+            assert_eq!(generate(seed, 10).validate(), Ok(()));
+        }
+    }
+}
@@ -0,0 +1,29 @@
+/// Positive if `a`, `b`, `c` turn counterclockwise, negative if clockwise, 0 if aligned.
+fn orientation(a: &(f32, f32), b: &(f32, f32), c: &(f32, f32)) -> f32 {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+/// True if segments `a1`-`a2` and `b1`-`b2` cross each other.
+///
+/// Segments only touching, by an end or because they are aligned, don't cross.
+pub fn segments_cross(a1: &(f32, f32), a2: &(f32, f32), b1: &(f32, f32), b2: &(f32, f32)) -> bool {
+    let opposite = |x: f32, y: f32| (x > 0f32 && y < 0f32) || (x < 0f32 && y > 0f32);
+    opposite(orientation(a1, a2, b1), orientation(a1, a2, b2))
+        && opposite(orientation(b1, b2, a1), orientation(b1, b2, a2))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn crossing_and_touching_segments() {
+        let (a1, a2) = ((0f32, 0f32), (10f32, 10f32));
+        assert!(segments_cross(&a1, &a2, &(0f32, 10f32), &(10f32, 0f32)));
+        assert!(!segments_cross(&a1, &a2, &(0f32, 10f32), &(4f32, 6f32)));
+        // Sharing an end.
+        assert!(!segments_cross(&a1, &a2, &a2, &(20f32, 0f32)));
+        // Aligned.
+        assert!(!segments_cross(&a1, &a2, &(5f32, 5f32), &(15f32, 15f32)));
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+
+use rand::seq::SliceRandom;
+
+use crate::{
+    generator::{MapGenerator, RoomCreated, RoomFactory, MIN_DISTANCE_BETWEEN_ROOMS},
+    map::{MapDef, MapError, Room, RoomId, RoomType},
+};
+
+/// Distance between the centers of neighbouring cells.
+const CELL_SIZE: f32 = MIN_DISTANCE_BETWEEN_ROOMS * 1.2f32;
+/// Axial coordinates of the 6 neighbours of cell (0, 0).
+const NEIGHBOURS: [(i32, i32); 6] = [(1, 0), (1, -1), (0, -1), (-1, 0), (-1, 1), (0, 1)];
+
+/// Center of the hexagonal cell with axial coordinates `cell`, for cells of `size`.
+pub(crate) fn cell_position(cell: (i32, i32), size: f32) -> (f32, f32) {
+    let (q, r) = (cell.0 as f32, cell.1 as f32);
+    (size * (q + r / 2f32), size * r * 3f32.sqrt() / 2f32)
+}
+
+/// Axial coordinates of the hexagonal cell of `size` containing `position`.
+pub(crate) fn cell_at(position: &(f32, f32), size: f32) -> (i32, i32) {
+    let r = position.1 * 2f32 / (3f32.sqrt() * size);
+    let q = position.0 / size - r / 2f32;
+    // Rounds in cube coordinates, where q + r + s = 0.
+    let s = -q - r;
+    let (mut rq, mut rr, rs) = (q.round(), r.round(), s.round());
+    let (dq, dr, ds) = ((rq - q).abs(), (rr - r).abs(), (rs - s).abs());
+    if dq > dr && dq > ds {
+        rq = -rr - rs;
+    } else if dr > ds {
+        rr = -rq - rs;
+    }
+    (rq as i32, rr as i32)
+}
+
+/// Neighbouring cells of `cell`.
+pub(crate) fn neighbour_cells(cell: (i32, i32)) -> impl Iterator<Item = (i32, i32)> {
+    NEIGHBOURS
+        .iter()
+        .map(move |(q, r)| (cell.0 + q, cell.1 + r))
+}
+
+/// Rooms on the cells of a hexagonal grid, each linked to some of its 6 neighbours.
+pub struct HexLattice<'a> {
+    factory: RoomFactory<'a>,
+}
+
+impl<'a> HexLattice<'a> {
+    pub fn new(factory: RoomFactory<'a>) -> Self {
+        Self { factory }
+    }
+}
+
+impl<'a> MapGenerator for HexLattice<'a> {
+    /// A safe room at the origin and one of its neighbours.
+    fn create_map(&mut self) -> MapDef {
+        let mut map = MapDef::default();
+        let first = *neighbour_cells((0, 0))
+            .collect::<Vec<_>>()
+            .choose(self.factory.rng())
+            .expect("a cell has neighbours");
+        for (i, cell) in [(0, 0), first].iter().enumerate() {
+            map.add_room(
+                RoomId(i),
+                Room::new(cell_position(*cell, CELL_SIZE), RoomType::safe()),
+            )
+            .expect("room ids are created in order");
+        }
+        map.connect(RoomId(0), RoomId(1)).expect("both rooms exist");
+        map
+    }
+
+    fn expand(
+        &mut self,
+        map: &mut MapDef,
+        from_room_id: RoomId,
+    ) -> Result<Vec<RoomCreated>, MapError> {
+        let cell = cell_at(&map.room(from_room_id)?.position, CELL_SIZE);
+        let mut free: Vec<(f32, f32)> = neighbour_cells(cell)
+            .map(|c| cell_position(c, CELL_SIZE))
+            // Rooms are on cell centers, a room closer than half a cell is on the cell.
+            .filter(|p| map.spatial().within_radius(p, CELL_SIZE / 2f32).is_empty())
+            .collect();
+        free.shuffle(self.factory.rng());
+        free.truncate(self.factory.rooms_to_create_on_move() as usize);
+
+        let mut created = vec![];
+        let mut duplicates = HashMap::default();
+        for position in free {
+            if !self.factory.can_link(map, from_room_id, &position) {
+                continue;
+            }
+            let room = match self.factory.pick_room(&position, &mut duplicates) {
+                Some(room) => room,
+                None => continue,
+            };
+            // Only the neighbouring cells are close enough to be linked.
+            created.push(self.factory.add_room(
+                map,
+                from_room_id,
+                position,
+                room,
+                CELL_SIZE * 1.1f32,
+            )?);
+        }
+        Ok(created)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        poisson::distance_squared, random::RandomDeterministic, room_chances::RoomChanceWeights,
+    };
+
+    #[test]
+    fn cells_round_trip() {
+        for cell in [(0, 0), (3, -2), (-5, 7), (10, 10)].iter() {
+            let position = cell_position(*cell, 10f32);
+            assert_eq!(cell_at(&position, 10f32), *cell);
+            assert_eq!(
+                cell_at(&(position.0 + 3f32, position.1 - 2f32), 10f32),
+                *cell
+            );
+        }
+    }
+    #[test]
+    fn rooms_stay_on_the_lattice() {
+        let mut random = RandomDeterministic::new(4);
+        let chances = RoomChanceWeights::default();
+        let mut generator = HexLattice::new(RoomFactory::new(&mut random, &chances));
+        let mut map = generator.create_map();
+        for i in 0..10 {
+            generator.expand(&mut map, RoomId(i)).unwrap();
+        }
+        for (_, room) in map.rooms() {
+            let on_cell = cell_position(cell_at(&room.position, CELL_SIZE), CELL_SIZE);
+            assert!(distance_squared(&on_cell, &room.position) < 0.01f32);
+            for connection in room.connections() {
+                let other = map.room(*connection).unwrap();
+                let length = distance_squared(&room.position, &other.position).sqrt();
+                assert!((length - CELL_SIZE).abs() < 0.01f32);
+            }
+        }
+    }
+}
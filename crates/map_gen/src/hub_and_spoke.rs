@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+
+use rand::Rng;
+
+use crate::{
+    generator::{is_free, MapGenerator, RoomCreated, RoomFactory, MIN_DISTANCE_BETWEEN_ROOMS},
+    hex_lattice::{cell_at, cell_position, neighbour_cells},
+    map::{MapDef, MapError, Room, RoomId, RoomType},
+    math::polar_offset,
+    poisson::distance_squared,
+};
+
+/// Distance between neighbouring hubs, hubs lie on a hexagonal grid of this size.
+const HUB_SPACING: f32 = MIN_DISTANCE_BETWEEN_ROOMS * 5f32;
+/// Distance between a hub and its spokes.
+const SPOKE_DISTANCE: f32 = MIN_DISTANCE_BETWEEN_ROOMS * 1.3f32;
+
+/// Hub the closest to `position`.
+fn hub_of(position: &(f32, f32)) -> (f32, f32) {
+    cell_position(cell_at(position, HUB_SPACING), HUB_SPACING)
+}
+
+fn is_hub(position: &(f32, f32)) -> bool {
+    distance_squared(&hub_of(position), position)
+        < MIN_DISTANCE_BETWEEN_ROOMS * MIN_DISTANCE_BETWEEN_ROOMS / 4f32
+}
+
+/// Hubs surrounded by spokes, a spoke leads to the next hub in its direction.
+pub struct HubAndSpoke<'a> {
+    factory: RoomFactory<'a>,
+}
+
+impl<'a> HubAndSpoke<'a> {
+    pub fn new(factory: RoomFactory<'a>) -> Self {
+        Self { factory }
+    }
+
+    /// Spokes around the hub at `hub`, at random angles.
+    fn spoke_positions(&mut self, map: &MapDef, hub: &(f32, f32)) -> Vec<(f32, f32)> {
+        let mut positions: Vec<(f32, f32)> = vec![];
+        let min_distance_squared = MIN_DISTANCE_BETWEEN_ROOMS * MIN_DISTANCE_BETWEEN_ROOMS;
+        for _ in 0..self.factory.rooms_to_create_on_move() {
+            let angle = self.factory.rng().gen::<f32>() * 2f32 * std::f32::consts::PI;
+            let position = polar_offset(hub, SPOKE_DISTANCE, angle);
+            if is_free(map, &position)
+                && positions
+                    .iter()
+                    .all(|p| distance_squared(p, &position) >= min_distance_squared)
+            {
+                positions.push(position);
+            }
+        }
+        positions
+    }
+
+    /// The hub next to the hub of `spoke`, in the direction of `spoke`, if it doesn't exist yet.
+    fn next_hub_position(&self, map: &MapDef, spoke: &(f32, f32)) -> Option<(f32, f32)> {
+        let hub = hub_of(spoke);
+        let direction = (spoke.0 - hub.0, spoke.1 - hub.1);
+        let alignment = |p: &(f32, f32)| (p.0 - hub.0) * direction.0 + (p.1 - hub.1) * direction.1;
+        let next = neighbour_cells(cell_at(&hub, HUB_SPACING))
+            .map(|c| cell_position(c, HUB_SPACING))
+            .fold(None, |best: Option<(f32, f32)>, p| match best {
+                Some(b) if alignment(&b) >= alignment(&p) => Some(b),
+                _ => Some(p),
+            })?;
+        Some(next).filter(|p| is_free(map, p))
+    }
+}
+
+impl<'a> MapGenerator for HubAndSpoke<'a> {
+    /// A safe hub at the origin and one of its spokes.
+    fn create_map(&mut self) -> MapDef {
+        let mut map = MapDef::default();
+        let angle = self.factory.rng().gen::<f32>() * 2f32 * std::f32::consts::PI;
+        let spoke = polar_offset(&(0f32, 0f32), SPOKE_DISTANCE, angle);
+        for (i, position) in [(0f32, 0f32), spoke].iter().enumerate() {
+            map.add_room(RoomId(i), Room::new(*position, RoomType::safe()))
+                .expect("room ids are created in order");
+        }
+        map.connect(RoomId(0), RoomId(1)).expect("both rooms exist");
+        map
+    }
+
+    fn expand(
+        &mut self,
+        map: &mut MapDef,
+        from_room_id: RoomId,
+    ) -> Result<Vec<RoomCreated>, MapError> {
+        let from = map.room(from_room_id)?.position;
+        let positions = if is_hub(&from) {
+            self.spoke_positions(map, &from)
+        } else {
+            self.next_hub_position(map, &from).into_iter().collect()
+        };
+        let mut created = vec![];
+        let mut duplicates = HashMap::default();
+        for position in positions {
+            if !self.factory.can_link(map, from_room_id, &position) {
+                continue;
+            }
+            let room = match self.factory.pick_room(&position, &mut duplicates) {
+                Some(room) => room,
+                None => continue,
+            };
+            // Spokes of the same hub may link to each other, hubs are too far from anything.
+            created.push(self.factory.add_room(
+                map,
+                from_room_id,
+                position,
+                room,
+                SPOKE_DISTANCE * 1.2f32,
+            )?);
+        }
+        Ok(created)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{random::RandomDeterministic, room_chances::RoomChanceWeights};
+
+    #[test]
+    fn spokes_lead_to_hubs() {
+        let mut random = RandomDeterministic::new(6);
+        let chances = RoomChanceWeights::default();
+        let mut generator = HubAndSpoke::new(RoomFactory::new(&mut random, &chances));
+        let mut map = generator.create_map();
+        for i in 0..12 {
+            generator.expand(&mut map, RoomId(i)).unwrap();
+        }
+        let hubs = map
+            .rooms()
+            .filter(|(_, room)| is_hub(&room.position))
+            .count();
+        assert!(hubs > 1);
+        for (_, room) in map.rooms() {
+            if is_hub(&room.position) {
+                continue;
+            }
+            let hub = hub_of(&room.position);
+            assert!(room
+                .connections()
+                .iter()
+                .any(|c| map.room(*c).unwrap().position == hub));
+        }
+    }
+}
@@ -0,0 +1,22 @@
+pub mod biome;
+pub mod configuration;
+pub mod connection;
+pub mod corridors;
+pub mod culling;
+pub mod danger_forecast;
+pub mod enemies;
+pub mod export;
+pub mod generator;
+pub mod geometry;
+pub mod hex_lattice;
+pub mod hub_and_spoke;
+pub mod map;
+pub mod math;
+pub mod pathfinding;
+pub mod poisson;
+pub mod random;
+pub mod room_chances;
+pub mod room_definitions;
+pub mod run_code;
+pub mod spatial;
+pub mod stats;
@@ -0,0 +1,369 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{poisson::distance_squared, spatial::SpatialIndex};
+
+/// Cell size of the [`SpatialIndex`] of a [`MapDef`], close to the distance between rooms.
+const SPATIAL_CELL_SIZE: f32 = 40f32;
+
+#[derive(
+    PartialEq, Eq, PartialOrd, Ord, Hash, Default, Clone, Copy, Debug, Serialize, Deserialize,
+)]
+pub struct RoomId(pub usize);
+
+/// Connections are only changed through [`MapDef::connect`] and [`MapDef::disconnect`],
+/// so they stay symmetric.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Room {
+    connections: Vec<RoomId>,
+    pub position: (f32, f32),
+    pub room_type: RoomType,
+    pub visited: bool,
+}
+
+impl Room {
+    pub fn new(position: (f32, f32), room_type: RoomType) -> Self {
+        Self {
+            connections: vec![],
+            position,
+            room_type,
+            visited: false,
+        }
+    }
+
+    pub fn connections(&self) -> &[RoomId] {
+        &self.connections
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MapError {
+    UnknownRoom(RoomId),
+    RoomAlreadyExists(RoomId),
+    SelfLoop(RoomId),
+    AlreadyConnected(RoomId, RoomId),
+    NotConnected(RoomId, RoomId),
+    /// The first room lists the second one, but not the other way around.
+    AsymmetricConnection(RoomId, RoomId),
+    DuplicateConnection(RoomId, RoomId),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::UnknownRoom(id) => write!(f, "room {} does not exist", id.0),
+            MapError::RoomAlreadyExists(id) => write!(f, "room {} already exists", id.0),
+            MapError::SelfLoop(id) => write!(f, "room {} is connected to itself", id.0),
+            MapError::AlreadyConnected(a, b) => {
+                write!(f, "rooms {} and {} are already connected", a.0, b.0)
+            }
+            MapError::NotConnected(a, b) => {
+                write!(f, "rooms {} and {} are not connected", a.0, b.0)
+            }
+            MapError::AsymmetricConnection(a, b) => {
+                write!(f, "room {} lists room {} but not the other way", a.0, b.0)
+            }
+            MapError::DuplicateConnection(a, b) => {
+                write!(f, "room {} lists room {} more than once", a.0, b.0)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(from = "MapData", into = "MapData")]
+pub struct MapDef {
+    /// Ordered, so iterating over rooms gives the same order on every platform.
+    rooms: BTreeMap<RoomId, Room>,
+    spatial: SpatialIndex,
+    /// Never decreases, so ids of removed rooms are not given again.
+    next_id: usize,
+    /// Length of the longest link ever made, kept when links are removed.
+    longest_link: f32,
+}
+
+impl Default for MapDef {
+    fn default() -> Self {
+        Self {
+            rooms: BTreeMap::default(),
+            spatial: SpatialIndex::new(SPATIAL_CELL_SIZE),
+            next_id: 0,
+            longest_link: 0f32,
+        }
+    }
+}
+
+impl MapDef {
+    pub fn len(&self) -> usize {
+        self.rooms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rooms.is_empty()
+    }
+
+    pub fn contains(&self, id: RoomId) -> bool {
+        self.rooms.contains_key(&id)
+    }
+
+    pub fn rooms(&self) -> impl Iterator<Item = (&RoomId, &Room)> {
+        self.rooms.iter()
+    }
+
+    pub fn room(&self, id: RoomId) -> Result<&Room, MapError> {
+        self.rooms.get(&id).ok_or(MapError::UnknownRoom(id))
+    }
+
+    pub fn room_mut(&mut self, id: RoomId) -> Result<&mut Room, MapError> {
+        self.rooms.get_mut(&id).ok_or(MapError::UnknownRoom(id))
+    }
+
+    pub fn neighbours(&self, id: RoomId) -> Result<&[RoomId], MapError> {
+        self.room(id).map(|r| r.connections())
+    }
+
+    pub fn spatial(&self) -> &SpatialIndex {
+        &self.spatial
+    }
+
+    /// No link of the map is longer, to know how far to look around a point for links.
+    pub fn longest_link(&self) -> f32 {
+        self.longest_link
+    }
+
+    /// Returns an id which was never used by this map, even by a removed room.
+    pub fn allocate_id(&mut self) -> RoomId {
+        let id = RoomId(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    /// Adds a room, its connections are ignored: use [`MapDef::connect`] afterwards.
+    pub fn add_room(&mut self, id: RoomId, mut room: Room) -> Result<(), MapError> {
+        if self.rooms.contains_key(&id) {
+            return Err(MapError::RoomAlreadyExists(id));
+        }
+        room.connections.clear();
+        self.next_id = self.next_id.max(id.0 + 1);
+        self.spatial.insert(id, room.position);
+        self.rooms.insert(id, room);
+        Ok(())
+    }
+
+    /// Removes a room and every connection to it.
+    pub fn remove_room(&mut self, id: RoomId) -> Result<Room, MapError> {
+        let room = self.rooms.remove(&id).ok_or(MapError::UnknownRoom(id))?;
+        for connection in room.connections.iter() {
+            if let Some(other) = self.rooms.get_mut(connection) {
+                other.connections.retain(|c| *c != id);
+            }
+        }
+        self.spatial.remove(id, room.position);
+        Ok(room)
+    }
+
+    pub fn connect(&mut self, a: RoomId, b: RoomId) -> Result<(), MapError> {
+        if a == b {
+            return Err(MapError::SelfLoop(a));
+        }
+        if self.neighbours(a)?.contains(&b) {
+            return Err(MapError::AlreadyConnected(a, b));
+        }
+        let length = distance_squared(&self.room(a)?.position, &self.room(b)?.position).sqrt();
+        self.longest_link = self.longest_link.max(length);
+        self.room_mut(a)?.connections.push(b);
+        self.room_mut(b)?.connections.push(a);
+        Ok(())
+    }
+
+    pub fn disconnect(&mut self, a: RoomId, b: RoomId) -> Result<(), MapError> {
+        if !self.neighbours(a)?.contains(&b) {
+            return Err(MapError::NotConnected(a, b));
+        }
+        self.room(b)?;
+        self.room_mut(a)?.connections.retain(|c| *c != b);
+        self.room_mut(b)?.connections.retain(|c| *c != a);
+        Ok(())
+    }
+
+    /// Checks that connections are symmetric, point to existing rooms,
+    /// and contain no self loop nor duplicate.
+    pub fn validate(&self) -> Result<(), Vec<MapError>> {
+        let mut errors = vec![];
+        for (id, room) in self.rooms.iter() {
+            for (i, connection) in room.connections.iter().enumerate() {
+                if connection == id {
+                    errors.push(MapError::SelfLoop(*id));
+                    continue;
+                }
+                if room.connections[..i].contains(connection) {
+                    errors.push(MapError::DuplicateConnection(*id, *connection));
+                    continue;
+                }
+                match self.rooms.get(connection) {
+                    None => errors.push(MapError::UnknownRoom(*connection)),
+                    Some(other) if !other.connections.contains(id) => {
+                        errors.push(MapError::AsymmetricConnection(*id, *connection))
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Serialized form of a [`MapDef`], its spatial index is rebuilt when loaded.
+#[derive(Serialize, Deserialize)]
+struct MapData {
+    rooms: BTreeMap<RoomId, Room>,
+    #[serde(default)]
+    next_id: usize,
+}
+
+impl From<MapData> for MapDef {
+    fn from(data: MapData) -> Self {
+        let mut map = MapDef {
+            next_id: data.next_id,
+            ..MapDef::default()
+        };
+        for (id, room) in data.rooms.into_iter() {
+            map.next_id = map.next_id.max(id.0 + 1);
+            map.spatial.insert(id, room.position);
+            map.rooms.insert(id, room);
+        }
+        for room in map.rooms.values() {
+            for connection in room.connections.iter() {
+                if let Some(other) = map.rooms.get(connection) {
+                    let length = distance_squared(&room.position, &other.position).sqrt();
+                    map.longest_link = map.longest_link.max(length);
+                }
+            }
+        }
+        map
+    }
+}
+
+impl From<MapDef> for MapData {
+    fn from(map: MapDef) -> Self {
+        MapData {
+            rooms: map.rooms,
+            next_id: map.next_id,
+        }
+    }
+}
+
+/// Name of a room type declared in [`crate::room_definitions::RoomDefinitions`].
+#[derive(Clone, PartialEq, Hash, Eq, Debug, Serialize, Deserialize)]
+pub struct RoomType(pub String);
+
+impl RoomType {
+    pub fn new(name: &str) -> Self {
+        Self(name.to_string())
+    }
+
+    /// Type of the starting rooms, and of rooms once visited.
+    pub fn safe() -> Self {
+        Self::new("Safe")
+    }
+}
+
+impl Default for RoomType {
+    fn default() -> Self {
+        Self::safe()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn map_with_rooms(count: usize) -> MapDef {
+        let mut map = MapDef::default();
+        for i in 0..count {
+            map.add_room(
+                RoomId(i),
+                Room::new((i as f32 * 50f32, 0f32), RoomType::safe()),
+            )
+            .unwrap();
+        }
+        map
+    }
+
+    #[test]
+    fn connect_and_disconnect() {
+        let mut map = map_with_rooms(3);
+        map.connect(RoomId(0), RoomId(1)).unwrap();
+        map.connect(RoomId(2), RoomId(1)).unwrap();
+        assert_eq!(map.neighbours(RoomId(1)).unwrap(), &[RoomId(0), RoomId(2)]);
+        assert_eq!(
+            map.connect(RoomId(1), RoomId(0)),
+            Err(MapError::AlreadyConnected(RoomId(1), RoomId(0)))
+        );
+        assert_eq!(
+            map.connect(RoomId(1), RoomId(1)),
+            Err(MapError::SelfLoop(RoomId(1)))
+        );
+        assert_eq!(
+            map.connect(RoomId(1), RoomId(5)),
+            Err(MapError::UnknownRoom(RoomId(5)))
+        );
+        map.disconnect(RoomId(1), RoomId(0)).unwrap();
+        assert_eq!(map.neighbours(RoomId(0)).unwrap(), &[]);
+        assert_eq!(map.validate(), Ok(()));
+    }
+    #[test]
+    fn longest_link_is_tracked() {
+        let mut map = map_with_rooms(3);
+        assert_eq!(map.longest_link(), 0f32);
+        map.connect(RoomId(0), RoomId(1)).unwrap();
+        map.connect(RoomId(0), RoomId(2)).unwrap();
+        map.connect(RoomId(1), RoomId(2)).unwrap();
+        assert_eq!(map.longest_link(), 100f32);
+        let loaded = MapDef::from(MapData::from(map.clone()));
+        assert_eq!(loaded.longest_link(), 100f32);
+    }
+    #[test]
+    fn removed_ids_are_not_allocated_again() {
+        let mut map = map_with_rooms(3);
+        map.connect(RoomId(0), RoomId(2)).unwrap();
+        map.connect(RoomId(1), RoomId(2)).unwrap();
+        map.remove_room(RoomId(2)).unwrap();
+        assert_eq!(map.neighbours(RoomId(0)).unwrap(), &[]);
+        assert_eq!(map.spatial().pick(&(100f32, 0f32), 5f32), None);
+        assert_eq!(map.validate(), Ok(()));
+        assert_eq!(map.allocate_id(), RoomId(3));
+        assert_eq!(
+            map.remove_room(RoomId(2)).err(),
+            Some(MapError::UnknownRoom(RoomId(2)))
+        );
+    }
+    #[test]
+    fn validate_reports_broken_connections() {
+        let mut map = map_with_rooms(3);
+        map.connect(RoomId(0), RoomId(1)).unwrap();
+        let room = map.rooms.get_mut(&RoomId(2)).unwrap();
+        room.connections.push(RoomId(2));
+        room.connections.push(RoomId(0));
+        room.connections.push(RoomId(0));
+        room.connections.push(RoomId(9));
+        let errors = map.validate().unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                MapError::SelfLoop(RoomId(2)),
+                MapError::AsymmetricConnection(RoomId(2), RoomId(0)),
+                MapError::DuplicateConnection(RoomId(2), RoomId(0)),
+                MapError::UnknownRoom(RoomId(9)),
+            ]
+        );
+    }
+}
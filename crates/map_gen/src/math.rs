@@ -0,0 +1,20 @@
+//! Trigonometry giving the same results on every platform.
+//!
+//! The `f32` methods of std call the platform math library, whose last bits differ between
+//! native and wasm32 builds, and a single bit is enough to move a room out of a Poisson disk.
+//! Generation uses these functions instead, implemented in Rust by `libm`.
+
+/// Sine and cosine of `angle`, in radians.
+pub fn sin_cos(angle: f32) -> (f32, f32) {
+    (libm::sinf(angle), libm::cosf(angle))
+}
+
+pub fn atan2(y: f32, x: f32) -> f32 {
+    libm::atan2f(y, x)
+}
+
+/// Point at `distance` from `origin` in the direction of `angle`.
+pub fn polar_offset(origin: &(f32, f32), distance: f32, angle: f32) -> (f32, f32) {
+    let (sin, cos) = sin_cos(angle);
+    (origin.0 + distance * cos, origin.1 + distance * sin)
+}
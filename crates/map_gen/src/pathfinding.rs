@@ -0,0 +1,254 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::{
+    map::{MapDef, MapError, RoomId, RoomType},
+    poisson::distance_squared,
+};
+
+/// Cost of walking between connected rooms, used by [`shortest_path`].
+pub trait EdgeCost {
+    /// Cost of walking from `from` to its connection `to`, `None` if it must not be walked.
+    fn cost(&self, map: &MapDef, from: RoomId, to: RoomId) -> Option<f32>;
+
+    /// Lower bound of the cost from `from` to `goal`, turns the search into A*.
+    /// The default makes it a Dijkstra search.
+    fn heuristic(&self, _map: &MapDef, _from: RoomId, _goal: RoomId) -> f32 {
+        0f32
+    }
+}
+
+/// Every connection costs the same, finds the route with the fewest moves.
+pub struct FewestMoves;
+
+impl EdgeCost for FewestMoves {
+    fn cost(&self, _map: &MapDef, _from: RoomId, _to: RoomId) -> Option<f32> {
+        Some(1f32)
+    }
+}
+
+/// Connections cost their length, finds the shortest route in world distance.
+pub struct WorldDistance;
+
+impl EdgeCost for WorldDistance {
+    fn cost(&self, map: &MapDef, from: RoomId, to: RoomId) -> Option<f32> {
+        Some(world_distance(map, from, to))
+    }
+
+    fn heuristic(&self, map: &MapDef, from: RoomId, goal: RoomId) -> f32 {
+        world_distance(map, from, goal)
+    }
+}
+
+/// Like [`WorldDistance`], with a penalty added to enter rooms of some types.
+pub struct AvoidRoomTypes {
+    pub room_types: Vec<RoomType>,
+    pub penalty: f32,
+}
+
+impl EdgeCost for AvoidRoomTypes {
+    fn cost(&self, map: &MapDef, from: RoomId, to: RoomId) -> Option<f32> {
+        let distance = world_distance(map, from, to);
+        let room_type = &map.room(to).ok()?.room_type;
+        if self.room_types.contains(room_type) {
+            Some(distance + self.penalty)
+        } else {
+            Some(distance)
+        }
+    }
+
+    fn heuristic(&self, map: &MapDef, from: RoomId, goal: RoomId) -> f32 {
+        world_distance(map, from, goal)
+    }
+}
+
+fn world_distance(map: &MapDef, from: RoomId, to: RoomId) -> f32 {
+    match (map.room(from), map.room(to)) {
+        (Ok(a), Ok(b)) => distance_squared(&a.position, &b.position).sqrt(),
+        _ => 0f32,
+    }
+}
+
+/// Rooms to walk through, from the start room to the goal included.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Path {
+    pub rooms: Vec<RoomId>,
+    pub cost: f32,
+}
+
+/// Open room of the search, the heap pops the lowest estimate first.
+struct Candidate {
+    estimate: f32,
+    id: RoomId,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed for a min-heap, ties broken by id to stay deterministic.
+        other
+            .estimate
+            .partial_cmp(&self.estimate)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| other.id.0.cmp(&self.id.0))
+    }
+}
+
+/// Cheapest route from `from` to `to` following connections, `None` if `to` can't be reached.
+pub fn shortest_path(
+    map: &MapDef,
+    from: RoomId,
+    to: RoomId,
+    edge_cost: &impl EdgeCost,
+) -> Result<Option<Path>, MapError> {
+    map.room(from)?;
+    map.room(to)?;
+    let mut costs: HashMap<RoomId, f32> = HashMap::default();
+    let mut previous: HashMap<RoomId, RoomId> = HashMap::default();
+    let mut open = BinaryHeap::new();
+    costs.insert(from, 0f32);
+    open.push(Candidate {
+        estimate: edge_cost.heuristic(map, from, to),
+        id: from,
+    });
+    while let Some(Candidate { id, estimate }) = open.pop() {
+        let cost = costs[&id];
+        if id == to {
+            let mut rooms = vec![to];
+            while let Some(p) = previous.get(rooms.last().unwrap()) {
+                rooms.push(*p);
+            }
+            rooms.reverse();
+            return Ok(Some(Path { rooms, cost }));
+        }
+        if estimate > cost + edge_cost.heuristic(map, id, to) {
+            // Already reached with a lower cost.
+            continue;
+        }
+        for connection in map.neighbours(id)? {
+            let step = match edge_cost.cost(map, id, *connection) {
+                Some(step) => step,
+                None => continue,
+            };
+            let new_cost = cost + step;
+            if matches!(costs.get(connection), Some(c) if *c <= new_cost) {
+                continue;
+            }
+            costs.insert(*connection, new_cost);
+            previous.insert(*connection, id);
+            open.push(Candidate {
+                estimate: new_cost + edge_cost.heuristic(map, *connection, to),
+                id: *connection,
+            });
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::map::Room;
+
+    /// A square 0-1-2-3 with a long detour: 0 to 2 goes through 1 (a danger room) or 3.
+    ///
+    /// ```text
+    /// 0 -- 1
+    /// |    |
+    /// 3 -- 2    4 (not connected)
+    /// ```
+    fn square_map() -> MapDef {
+        let mut map = MapDef::default();
+        let positions = [(0f32, 0f32), (10f32, 0f32), (10f32, 10f32), (0f32, 11f32)];
+        for (i, position) in positions.iter().enumerate() {
+            map.add_room(RoomId(i), Room::new(*position, RoomType::safe()))
+                .unwrap();
+        }
+        map.add_room(RoomId(4), Room::new((50f32, 0f32), RoomType::safe()))
+            .unwrap();
+        map.room_mut(RoomId(1)).unwrap().room_type = RoomType::new("Danger");
+        for (a, b) in [(0, 1), (1, 2), (2, 3), (3, 0)].iter() {
+            map.connect(RoomId(*a), RoomId(*b)).unwrap();
+        }
+        map
+    }
+
+    #[test]
+    fn shortest_path_follows_distance() {
+        let map = square_map();
+        let path = shortest_path(&map, RoomId(0), RoomId(2), &WorldDistance)
+            .unwrap()
+            .unwrap();
+        assert_eq!(path.rooms, vec![RoomId(0), RoomId(1), RoomId(2)]);
+        assert_eq!(path.cost, 20f32);
+    }
+    #[test]
+    fn avoided_rooms_make_detours() {
+        let map = square_map();
+        let avoid = AvoidRoomTypes {
+            room_types: vec![RoomType::new("Danger")],
+            penalty: 100f32,
+        };
+        let path = shortest_path(&map, RoomId(0), RoomId(2), &avoid)
+            .unwrap()
+            .unwrap();
+        assert_eq!(path.rooms, vec![RoomId(0), RoomId(3), RoomId(2)]);
+    }
+    #[test]
+    fn unreachable_and_unknown_rooms() {
+        let map = square_map();
+        assert_eq!(
+            shortest_path(&map, RoomId(0), RoomId(4), &FewestMoves).unwrap(),
+            None
+        );
+        assert_eq!(
+            shortest_path(&map, RoomId(0), RoomId(9), &FewestMoves),
+            Err(MapError::UnknownRoom(RoomId(9)))
+        );
+        let path = shortest_path(&map, RoomId(2), RoomId(2), &FewestMoves)
+            .unwrap()
+            .unwrap();
+        assert_eq!(path.rooms, vec![RoomId(2)]);
+    }
+    #[test]
+    fn a_star_matches_dijkstra_on_generated_maps() {
+        use crate::{
+            generator::{MapGenerator, RingExpansion, RoomFactory},
+            random::RandomDeterministic,
+            room_chances::RoomChanceWeights,
+        };
+        struct DistanceWithoutHeuristic;
+        impl EdgeCost for DistanceWithoutHeuristic {
+            fn cost(&self, map: &MapDef, from: RoomId, to: RoomId) -> Option<f32> {
+                WorldDistance.cost(map, from, to)
+            }
+        }
+        let mut random = RandomDeterministic::new(5);
+        let chances = RoomChanceWeights::default();
+        let mut generator = RingExpansion::new(RoomFactory::new(&mut random, &chances));
+        let mut map = generator.create_map();
+        for i in 0..15 {
+            generator.expand(&mut map, RoomId(i)).unwrap();
+        }
+        for (goal, _) in map.rooms() {
+            let a_star = shortest_path(&map, RoomId(0), *goal, &WorldDistance).unwrap();
+            let dijkstra =
+                shortest_path(&map, RoomId(0), *goal, &DistanceWithoutHeuristic).unwrap();
+            let (a_star, dijkstra) = (a_star.unwrap(), dijkstra.unwrap());
+            assert!((a_star.cost - dijkstra.cost).abs() < 0.001f32);
+        }
+    }
+}
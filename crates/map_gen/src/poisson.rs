@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+
+use rand::Rng;
+
+use crate::math::polar_offset;
+
+/// Poisson-disk sampler following Bridson's algorithm.
+///
+/// Candidates are drawn around a point at a random distance in `[radius, 2 * radius]`,
+/// accepted points are stored in a background grid whose cells are small enough to hold
+/// at most one point, so checking a candidate only visits the neighbouring cells.
+/// The grid is sparse so the sampled area is unbounded.
+pub struct Poisson {
+    radius: f32,
+    cell_size: f32,
+    grid: HashMap<(i32, i32), (f32, f32)>,
+}
+
+impl Poisson {
+    pub fn new(radius: f32) -> Self {
+        Poisson {
+            radius,
+            cell_size: radius / std::f32::consts::SQRT_2,
+            grid: HashMap::default(),
+        }
+    }
+
+    pub fn from_points(points: &[(f32, f32)], radius: f32) -> Self {
+        let mut poisson = Self::new(radius);
+        for p in points.iter() {
+            poisson.insert(*p);
+        }
+        poisson
+    }
+
+    pub fn insert(&mut self, point: (f32, f32)) {
+        self.grid.insert(self.cell(&point), point);
+    }
+
+    /// Returns true if no point stored is closer than `radius` to `point`.
+    pub fn is_free(&self, point: &(f32, f32)) -> bool {
+        let (cx, cy) = self.cell(point);
+        let radius_squared = self.radius * self.radius;
+        for x in cx - 2..=cx + 2 {
+            for y in cy - 2..=cy + 2 {
+                if let Some(existing_point) = self.grid.get(&(x, y)) {
+                    if distance_squared(existing_point, point) < radius_squared {
+                        return false;
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Tries `nb_attempts` random candidates around `near_point`, returns the first one far
+    /// enough from every stored point. The accepted point is not inserted.
+    pub fn compute_new_position(
+        &self,
+        near_point: &(f32, f32),
+        nb_attempts: u32,
+        random: impl Rng,
+    ) -> Option<(f32, f32)> {
+        sample_near(near_point, self.radius, nb_attempts, random, |p| {
+            self.is_free(p)
+        })
+    }
+
+    fn cell(&self, point: &(f32, f32)) -> (i32, i32) {
+        (
+            (point.0 / self.cell_size).floor() as i32,
+            (point.1 / self.cell_size).floor() as i32,
+        )
+    }
+}
+
+/// Tries `nb_attempts` random candidates at a distance between `radius` and `2 * radius` of
+/// `near_point`, returns the first one accepted by `is_free`.
+pub fn sample_near(
+    near_point: &(f32, f32),
+    radius: f32,
+    nb_attempts: u32,
+    mut random: impl Rng,
+    is_free: impl Fn(&(f32, f32)) -> bool,
+) -> Option<(f32, f32)> {
+    for _ in 0..nb_attempts {
+        let theta = random.gen::<f32>() * 2_f32 * std::f32::consts::PI;
+        // Uniform over the annulus area rather than over its radius.
+        let distance = radius * (1f32 + 3f32 * random.gen::<f32>()).sqrt();
+        let test_point = polar_offset(near_point, distance, theta);
+        if is_free(&test_point) {
+            return Some(test_point);
+        }
+    }
+    None
+}
+
+pub fn distance_squared(p1: &(f32, f32), p2: &(f32, f32)) -> f32 {
+    let dx = p2.0 - p1.0;
+    let dy = p2.1 - p1.1;
+    dx * dx + dy * dy
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    fn sample(seed: u64, count: usize) -> Vec<(f32, f32)> {
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        let mut poisson = Poisson::new(10f32);
+        let mut points = vec![(0f32, 0f32)];
+        poisson.insert(points[0]);
+        let mut active = 0;
+        while points.len() < count && active < points.len() {
+            match poisson.compute_new_position(&points[active], 30, &mut rng) {
+                Some(p) => {
+                    poisson.insert(p);
+                    points.push(p);
+                }
+                None => active += 1,
+            }
+        }
+        points
+    }
+
+    #[test]
+    fn points_respect_radius() {
+        let points = sample(3, 300);
+        assert_eq!(points.len(), 300);
+        for (i, p1) in points.iter().enumerate() {
+            for p2 in points[i + 1..].iter() {
+                assert!(distance_squared(p1, p2) >= 100f32);
+            }
+        }
+    }
+    #[test]
+    fn same_seed_same_points() {
+        assert_eq!(sample(8, 50), sample(8, 50));
+    }
+    #[test]
+    fn from_points_rejects_close_candidates() {
+        let poisson = Poisson::from_points(&[(0f32, 0f32), (25f32, 0f32)], 10f32);
+        assert!(!poisson.is_free(&(5f32, 5f32)));
+        assert!(!poisson.is_free(&(30f32, 0f32)));
+        assert!(poisson.is_free(&(12.5f32, 0f32)));
+    }
+}
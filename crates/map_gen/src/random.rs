@@ -0,0 +1,102 @@
+use std::collections::BTreeMap;
+
+use rand::{thread_rng, Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+
+/// Independent sequences of random numbers derived from the same seed: drawing more numbers
+/// from one stream never changes what the others draw.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum RandomStream {
+    /// Where rooms are placed and which ones are linked.
+    Layout,
+    RoomTypes,
+    Combat,
+    Danger,
+    Loot,
+    /// Which enemy guards a room.
+    Enemies,
+}
+
+impl RandomStream {
+    /// ChaCha stream of each [`RandomStream`], existing numbers must never change.
+    fn number(self) -> u64 {
+        match self {
+            RandomStream::Layout => 1,
+            RandomStream::RoomTypes => 2,
+            RandomStream::Combat => 3,
+            RandomStream::Danger => 4,
+            RandomStream::Loot => 5,
+            RandomStream::Enemies => 6,
+        }
+    }
+}
+
+/// Seeded random number generator, its state is serializable so a run can continue exactly.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RandomDeterministic {
+    /// Streams drawn from so far, the others are created from the seed when first used.
+    streams: BTreeMap<RandomStream, ChaCha20Rng>,
+    pub seed: u64,
+}
+
+impl Default for RandomDeterministic {
+    fn default() -> Self {
+        let seed = thread_rng().gen::<u64>();
+        Self::new(seed)
+    }
+}
+
+impl RandomDeterministic {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            streams: BTreeMap::default(),
+            seed,
+        }
+    }
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.streams.clear();
+    }
+    pub fn stream(&mut self, stream: RandomStream) -> &mut ChaCha20Rng {
+        let seed = self.seed;
+        self.streams.entry(stream).or_insert_with(|| {
+            let mut random = ChaCha20Rng::seed_from_u64(seed);
+            random.set_stream(stream.number());
+            random
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn streams_are_independent() {
+        let mut a = RandomDeterministic::new(7);
+        let mut b = RandomDeterministic::new(7);
+        for _ in 0..10 {
+            a.stream(RandomStream::Combat).gen::<u32>();
+        }
+        assert_eq!(
+            a.stream(RandomStream::Layout).gen::<u64>(),
+            b.stream(RandomStream::Layout).gen::<u64>()
+        );
+        assert_ne!(
+            a.stream(RandomStream::Combat).gen::<u64>(),
+            b.stream(RandomStream::Combat).gen::<u64>()
+        );
+        assert_ne!(
+            b.stream(RandomStream::Danger).gen::<u64>(),
+            b.stream(RandomStream::Loot).gen::<u64>()
+        );
+    }
+    #[test]
+    fn set_seed_restarts_streams() {
+        let mut random = RandomDeterministic::new(3);
+        let first = random.stream(RandomStream::RoomTypes).gen::<u64>();
+        random.set_seed(3);
+        assert_eq!(random.stream(RandomStream::RoomTypes).gen::<u64>(), first);
+    }
+}
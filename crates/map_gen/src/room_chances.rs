@@ -0,0 +1,127 @@
+use std::{convert::TryFrom, fmt};
+
+use rand::distributions::WeightedIndex;
+
+use crate::{map::RoomType, room_definitions::RoomDefinitions};
+
+/// Chances to generate each room type, `weights` and `definitions` have the same length.
+#[derive(Clone)]
+pub struct RoomChanceWeights {
+    pub weights: Vec<usize>,
+    /// Samples with `u32` rather than `usize`, which draws differently on 32 bits platforms.
+    pub weighted_index: WeightedIndex<u32>,
+    pub definitions: Vec<RoomDefinition>,
+    pub rooms_to_create_on_move: u32,
+}
+
+/// Why weights can't be used to draw room types.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WeightsError {
+    /// Every weight is 0, or there is no weight at all.
+    NoWeight,
+    /// The weights add up to more than `u32::MAX`.
+    TooLarge,
+}
+
+impl fmt::Display for WeightsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WeightsError::NoWeight => write!(f, "every room weight is 0"),
+            WeightsError::TooLarge => {
+                write!(f, "room weights add up to more than {}", u32::MAX)
+            }
+        }
+    }
+}
+
+impl std::error::Error for WeightsError {}
+
+impl RoomChanceWeights {
+    pub fn new(
+        weights: Vec<usize>,
+        definitions: Vec<RoomDefinition>,
+    ) -> Result<Self, WeightsError> {
+        Ok(Self {
+            weighted_index: weighted_index(&weights)?,
+            weights,
+            definitions,
+            rooms_to_create_on_move: 5,
+        })
+    }
+
+    /// Replaces the weights, nothing is changed if they can't be used.
+    pub fn set_weights(&mut self, weights: Vec<usize>) -> Result<(), WeightsError> {
+        self.weighted_index = weighted_index(&weights)?;
+        self.weights = weights;
+        Ok(())
+    }
+
+    /// Changes the weight of `room_type`, if it is one of the definitions.
+    pub fn with_weight(
+        mut self,
+        room_type: &RoomType,
+        weight: usize,
+    ) -> Result<Self, WeightsError> {
+        if let Some(index) = self
+            .definitions
+            .iter()
+            .position(|d| &d.type_room == room_type)
+        {
+            let mut weights = self.weights.clone();
+            weights[index] = weight;
+            self.set_weights(weights)?;
+        }
+        Ok(self)
+    }
+}
+
+fn weighted_index(weights: &[usize]) -> Result<WeightedIndex<u32>, WeightsError> {
+    // Checked here, `WeightedIndex` would overflow while adding them.
+    let weights = weights
+        .iter()
+        .map(|w| u32::try_from(*w))
+        .collect::<Result<Vec<u32>, _>>()
+        .map_err(|_| WeightsError::TooLarge)?;
+    weights
+        .iter()
+        .try_fold(0u32, |total, w| total.checked_add(*w))
+        .ok_or(WeightsError::TooLarge)?;
+    WeightedIndex::new(weights).map_err(|_| WeightsError::NoWeight)
+}
+
+#[derive(Clone)]
+pub struct RoomDefinition {
+    pub type_room: RoomType,
+    pub battle_chance: f64,
+    pub max_rooms_create: u32,
+}
+
+impl Default for RoomChanceWeights {
+    fn default() -> Self {
+        RoomDefinitions::default()
+            .chances(5)
+            .expect("default room types have weights")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unusable_weights_are_refused() {
+        let mut chances = RoomChanceWeights::default();
+        let weights = chances.weights.clone();
+        assert_eq!(
+            chances.set_weights(vec![0; weights.len()]),
+            Err(WeightsError::NoWeight)
+        );
+        assert_eq!(
+            chances.set_weights(vec![u32::MAX as usize, 1, 0, 0]),
+            Err(WeightsError::TooLarge)
+        );
+        assert_eq!(chances.weights, weights);
+        assert!(chances.set_weights(vec![0, 1, 0, 0]).is_ok());
+        assert_eq!(chances.weights, vec![0, 1, 0, 0]);
+    }
+}
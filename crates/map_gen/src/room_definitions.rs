@@ -0,0 +1,259 @@
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    biome::{BiomeDefinition, RoomOverride},
+    enemies::{DifficultyCurve, EnemyAbility, EnemyTemplate},
+    map::RoomType,
+    room_chances::{RoomChanceWeights, RoomDefinition, WeightsError},
+};
+
+/// What happens when the player enters a room, effects of a room are applied in order.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum RoomEffect {
+    AddCoins(u32),
+    /// The player needs this amount of coins to enter the room, it is then removed.
+    PayCoins(u32),
+    /// Spawns a danger zone next to the room, towards its connections.
+    SpawnDangerZone,
+    /// Multiplies the growth speed of every danger zone.
+    ScaleDangerSpeed(f32),
+}
+
+/// A room type, as declared by designers.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RoomTypeDefinition {
+    pub name: String,
+    /// RGB, each component between 0 and 1.
+    pub color: [f32; 3],
+    pub weight: usize,
+    pub battle_chance: f64,
+    pub max_rooms_create: u32,
+    #[serde(default)]
+    pub effects: Vec<RoomEffect>,
+}
+
+impl RoomTypeDefinition {
+    pub fn room_type(&self) -> RoomType {
+        RoomType::new(&self.name)
+    }
+
+    /// Coins needed to enter the room.
+    pub fn price(&self) -> u32 {
+        self.effects
+            .iter()
+            .map(|e| match e {
+                RoomEffect::PayCoins(amount) => *amount,
+                _ => 0,
+            })
+            .sum()
+    }
+}
+
+/// Every room type and enemy of the game, usually loaded from a `.rooms.ron` file.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RoomDefinitions {
+    pub rooms: Vec<RoomTypeDefinition>,
+    /// Enemies guarding rooms with a battle.
+    #[serde(default)]
+    pub enemies: Vec<EnemyTemplate>,
+    #[serde(default)]
+    pub difficulty: DifficultyCurve,
+    /// Angular sectors of the world, see [`crate::biome::Biomes`].
+    #[serde(default)]
+    pub biomes: Vec<BiomeDefinition>,
+}
+
+impl RoomDefinitions {
+    pub fn get(&self, room_type: &RoomType) -> Option<&RoomTypeDefinition> {
+        self.rooms.iter().find(|r| r.name == room_type.0)
+    }
+
+    /// Changes with any value of the definitions, so a recorded run can tell if it is replayed
+    /// with the definitions it was played with. The same on every platform.
+    pub fn fingerprint(&self) -> u64 {
+        let bytes = bincode::DefaultOptions::new()
+            .serialize(self)
+            .expect("room definitions are always serializable");
+        // FNV-1a, unlike `DefaultHasher` it doesn't change between Rust versions.
+        bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+
+    /// Chances to generate each room type.
+    pub fn chances(&self, rooms_to_create_on_move: u32) -> Result<RoomChanceWeights, WeightsError> {
+        let mut chances = RoomChanceWeights::new(
+            self.rooms.iter().map(|r| r.weight).collect(),
+            self.rooms
+                .iter()
+                .map(|r| RoomDefinition {
+                    type_room: r.room_type(),
+                    battle_chance: r.battle_chance,
+                    max_rooms_create: r.max_rooms_create,
+                })
+                .collect(),
+        )?;
+        chances.rooms_to_create_on_move = rooms_to_create_on_move;
+        Ok(chances)
+    }
+}
+
+impl Default for RoomDefinitions {
+    fn default() -> Self {
+        Self {
+            rooms: vec![
+                RoomTypeDefinition {
+                    name: "Danger".to_string(),
+                    color: [1f32, 0.27f32, 0f32],
+                    weight: 30,
+                    battle_chance: 0.1f64,
+                    max_rooms_create: 1,
+                    effects: vec![RoomEffect::SpawnDangerZone],
+                },
+                RoomTypeDefinition {
+                    name: "Safe".to_string(),
+                    color: [1f32, 1f32, 1f32],
+                    weight: 50,
+                    battle_chance: 0.3f64,
+                    max_rooms_create: 2,
+                    effects: vec![],
+                },
+                RoomTypeDefinition {
+                    name: "Coins".to_string(),
+                    color: [0f32, 1f32, 0f32],
+                    weight: 40,
+                    battle_chance: 0.5f64,
+                    max_rooms_create: 1,
+                    effects: vec![RoomEffect::AddCoins(1)],
+                },
+                RoomTypeDefinition {
+                    name: "Price".to_string(),
+                    color: [1f32, 0f32, 1f32],
+                    weight: 20,
+                    battle_chance: 0.2f64,
+                    max_rooms_create: 1,
+                    effects: vec![
+                        RoomEffect::PayCoins(7),
+                        RoomEffect::ScaleDangerSpeed(0.5f32),
+                    ],
+                },
+            ],
+            enemies: vec![
+                EnemyTemplate {
+                    name: "Slime".to_string(),
+                    hp: 1f32,
+                    attack: 1f32,
+                    reward: 0,
+                    min_level: 0,
+                    ability: Some(EnemyAbility::Regeneration(0.5f32)),
+                },
+                EnemyTemplate {
+                    name: "Bandit".to_string(),
+                    hp: 2f32,
+                    attack: 1f32,
+                    reward: 1,
+                    min_level: 1,
+                    ability: Some(EnemyAbility::FirstStrike),
+                },
+                EnemyTemplate {
+                    name: "Knight".to_string(),
+                    hp: 4f32,
+                    attack: 2f32,
+                    reward: 3,
+                    min_level: 3,
+                    ability: Some(EnemyAbility::Armor(0.5f32)),
+                },
+            ],
+            difficulty: DifficultyCurve::default(),
+            biomes: vec![
+                BiomeDefinition {
+                    name: "Plains".to_string(),
+                    tint: [0f32, 1f32, 1f32],
+                    danger_speed_multiplier: 1f32,
+                    rooms: vec![],
+                },
+                BiomeDefinition {
+                    name: "Mines".to_string(),
+                    tint: [1f32, 0.84f32, 0f32],
+                    danger_speed_multiplier: 1.2f32,
+                    rooms: weight_overrides([20, 30, 80, 10]),
+                },
+                BiomeDefinition {
+                    name: "Wastes".to_string(),
+                    tint: [1f32, 0.27f32, 0f32],
+                    danger_speed_multiplier: 1.5f32,
+                    rooms: weight_overrides([70, 40, 20, 10]),
+                },
+                BiomeDefinition {
+                    name: "Market".to_string(),
+                    tint: [1f32, 0f32, 1f32],
+                    danger_speed_multiplier: 0.8f32,
+                    rooms: weight_overrides([10, 50, 30, 60]),
+                },
+            ],
+        }
+    }
+}
+
+/// New weights of the default room types, in declaration order.
+fn weight_overrides(weights: [usize; 4]) -> Vec<RoomOverride> {
+    ["Danger", "Safe", "Coins", "Price"]
+        .iter()
+        .zip(weights.iter())
+        .map(|(name, weight)| RoomOverride {
+            name: name.to_string(),
+            weight: Some(*weight),
+            battle_chance: None,
+            max_rooms_create: None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn default_asset_matches_default_definitions() {
+        let asset: RoomDefinitions =
+            ron::de::from_str(include_str!("../../logic/assets/default.rooms.ron")).unwrap();
+        assert_eq!(asset, RoomDefinitions::default());
+    }
+    #[test]
+    fn fingerprint_follows_changes() {
+        let definitions = RoomDefinitions::default();
+        assert_eq!(
+            definitions.fingerprint(),
+            RoomDefinitions::default().fingerprint()
+        );
+        let mut cheaper = RoomDefinitions::default();
+        cheaper.rooms[3].effects[0] = RoomEffect::PayCoins(6);
+        assert_ne!(definitions.fingerprint(), cheaper.fingerprint());
+        let mut weaker = RoomDefinitions::default();
+        weaker.enemies[0].attack = 0.5f32;
+        assert_ne!(definitions.fingerprint(), weaker.fingerprint());
+    }
+    #[test]
+    fn price_sums_paid_coins() {
+        let definitions = RoomDefinitions::default();
+        let price = definitions.get(&RoomType::new("Price")).unwrap();
+        assert_eq!(price.price(), 7);
+        assert_eq!(definitions.get(&RoomType::safe()).unwrap().price(), 0);
+        assert!(definitions.get(&RoomType::new("Unknown")).is_none());
+    }
+    #[test]
+    fn chances_need_a_weight() {
+        let mut definitions = RoomDefinitions::default();
+        let chances = definitions.chances(3).unwrap();
+        assert_eq!(chances.weights, vec![30, 50, 40, 20]);
+        assert_eq!(chances.rooms_to_create_on_move, 3);
+        for room in definitions.rooms.iter_mut() {
+            room.weight = 0;
+        }
+        assert!(matches!(
+            definitions.chances(3),
+            Err(WeightsError::NoWeight)
+        ));
+    }
+}
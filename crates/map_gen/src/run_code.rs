@@ -0,0 +1,248 @@
+use std::fmt;
+
+use bincode::Options;
+use data_encoding::BASE32_NOPAD;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    configuration::MapConfiguration,
+    generator::{is_supported_version, GENERATOR_VERSION},
+    random::RandomDeterministic,
+    room_chances::{RoomChanceWeights, WeightsError},
+};
+
+/// Bumped whenever the binary layout of [`RunCode`] changes. Bincode ignores
+/// `#[serde(default)]`, so any field added to [`MapConfiguration`] changes the layout.
+const FORMAT_VERSION: u8 = 1;
+
+/// Everything the maps of a run depend on, shareable as a short text, see [`RunCode::encode`].
+///
+/// To stay short, the code only has the weight of each room type. The other values of the
+/// room types, such as their battle chance or how many can be created at once, come from the
+/// room definitions of whoever applies the code, as well as the biomes and enemies.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RunCode {
+    /// [`GENERATOR_VERSION`] when the code was made, other versions create other maps.
+    pub generator_version: u32,
+    pub seed: u64,
+    pub configuration: MapConfiguration,
+    pub room_weights: Vec<usize>,
+    pub rooms_to_create_on_move: u32,
+}
+
+#[derive(Debug)]
+pub enum RunCodeError {
+    /// Not base32, or empty.
+    NotACode,
+    UnsupportedFormat(u8),
+    Invalid(bincode::Error),
+    /// Made with a generator version this build can't reproduce.
+    UnsupportedGenerator(u32),
+    /// Made with another number of room types than the ones loaded.
+    OtherRoomTypes {
+        code: usize,
+        loaded: usize,
+    },
+    /// The room weights of the code can't generate rooms.
+    RoomWeights(WeightsError),
+}
+
+impl fmt::Display for RunCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunCodeError::NotACode => write!(f, "not a run code"),
+            RunCodeError::UnsupportedFormat(v) => write!(
+                f,
+                "unsupported run code format {} (expected {})",
+                v, FORMAT_VERSION
+            ),
+            RunCodeError::Invalid(err) => write!(f, "invalid run code: {}", err),
+            RunCodeError::UnsupportedGenerator(v) => write!(
+                f,
+                "run code from generator version {}, supported up to {}",
+                v, GENERATOR_VERSION
+            ),
+            RunCodeError::OtherRoomTypes { code, loaded } => write!(
+                f,
+                "run code made with {} room types, {} are loaded",
+                code, loaded
+            ),
+            RunCodeError::RoomWeights(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for RunCodeError {}
+
+impl From<WeightsError> for RunCodeError {
+    fn from(e: WeightsError) -> Self {
+        RunCodeError::RoomWeights(e)
+    }
+}
+
+impl RunCode {
+    pub fn new(
+        generator_version: u32,
+        seed: u64,
+        configuration: &MapConfiguration,
+        chances: &RoomChanceWeights,
+    ) -> Self {
+        Self {
+            generator_version,
+            seed,
+            configuration: configuration.clone(),
+            room_weights: chances.weights.clone(),
+            rooms_to_create_on_move: chances.rooms_to_create_on_move,
+        }
+    }
+
+    /// Uppercase base32 of a format version byte followed by the code in a compact binary form.
+    pub fn encode(&self) -> String {
+        let mut bytes = vec![FORMAT_VERSION];
+        bytes.extend(
+            bincode::DefaultOptions::new()
+                .serialize(self)
+                .expect("a run code is always serializable"),
+        );
+        BASE32_NOPAD.encode(&bytes)
+    }
+
+    /// Reads a code made by [`RunCode::encode`], ignoring case, spaces and dashes.
+    pub fn decode(code: &str) -> Result<Self, RunCodeError> {
+        let cleaned: String = code
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '-')
+            .map(|c| c.to_ascii_uppercase())
+            .collect();
+        let bytes = BASE32_NOPAD
+            .decode(cleaned.as_bytes())
+            .map_err(|_| RunCodeError::NotACode)?;
+        let code: RunCode = match bytes.split_first() {
+            Some((&FORMAT_VERSION, rest)) => bincode::DefaultOptions::new()
+                .deserialize(rest)
+                .map_err(RunCodeError::Invalid)?,
+            Some((version, _)) => return Err(RunCodeError::UnsupportedFormat(*version)),
+            None => return Err(RunCodeError::NotACode),
+        };
+        if !is_supported_version(code.generator_version) {
+            return Err(RunCodeError::UnsupportedGenerator(code.generator_version));
+        }
+        Ok(code)
+    }
+
+    /// Restores the seed and tuning values of the code.
+    /// Nothing is changed if the weights of the code don't fit the loaded room types.
+    pub fn apply(
+        &self,
+        random: &mut RandomDeterministic,
+        configuration: &mut MapConfiguration,
+        chances: &mut RoomChanceWeights,
+    ) -> Result<(), RunCodeError> {
+        if self.room_weights.len() != chances.definitions.len() {
+            return Err(RunCodeError::OtherRoomTypes {
+                code: self.room_weights.len(),
+                loaded: chances.definitions.len(),
+            });
+        }
+        chances.set_weights(self.room_weights.clone())?;
+        random.set_seed(self.seed);
+        *configuration = self.configuration.clone();
+        chances.rooms_to_create_on_move = self.rooms_to_create_on_move;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{culling::CullSettings, generator::Layout, map::RoomType};
+
+    #[test]
+    fn codes_round_trip() {
+        let mut configuration = MapConfiguration {
+            layout: Layout::HexLattice,
+            loop_density: 0.25f32,
+            culling: CullSettings {
+                max_graph_distance: None,
+                max_world_distance: Some(300f32),
+            },
+            ..MapConfiguration::default()
+        };
+        let chances = RoomChanceWeights::default()
+            .with_weight(&RoomType::safe(), 3)
+            .unwrap();
+        let code = RunCode::new(GENERATOR_VERSION, 123456789, &configuration, &chances);
+        let text = code.encode();
+        assert!(text
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()));
+        assert_eq!(RunCode::decode(&text).unwrap(), code);
+        let messy = format!(" {}-{} ", &text[..5].to_lowercase(), &text[5..]);
+        assert_eq!(RunCode::decode(&messy).unwrap(), code);
+
+        let mut random = RandomDeterministic::new(0);
+        let mut other_chances = RoomChanceWeights::default();
+        configuration.layout = Layout::RingExpansion;
+        code.apply(&mut random, &mut configuration, &mut other_chances)
+            .unwrap();
+        assert_eq!(random.seed, 123456789);
+        assert_eq!(configuration, code.configuration);
+        assert_eq!(other_chances.weights, chances.weights);
+
+        let mut fewer_types = RoomChanceWeights::default();
+        fewer_types.weights.pop();
+        fewer_types.definitions.pop();
+        assert!(matches!(
+            code.apply(&mut random, &mut configuration, &mut fewer_types),
+            Err(RunCodeError::OtherRoomTypes { code: 4, loaded: 3 })
+        ));
+        let mut no_weight = code.clone();
+        no_weight.room_weights = vec![0; 4];
+        assert!(matches!(
+            no_weight.apply(&mut random, &mut configuration, &mut other_chances),
+            Err(RunCodeError::RoomWeights(WeightsError::NoWeight))
+        ));
+    }
+    /// Codes already shared must keep decoding to the same run. If this fails after a change
+    /// of [`MapConfiguration`], bump [`FORMAT_VERSION`] and update the pinned code.
+    #[test]
+    fn encoded_layout_is_pinned() {
+        const PINNED: &str = "AEBCUAONZTGD2AAAEBAQAAAAAAAAAAAAAAAAAAAAAAAAAAAAACAD6AAEDYZCQFAF";
+        let code = RunCode::new(
+            2,
+            42,
+            &MapConfiguration::default(),
+            &RoomChanceWeights::default(),
+        );
+        assert_eq!(code.encode(), PINNED);
+        assert_eq!(RunCode::decode(PINNED).unwrap(), code);
+    }
+    #[test]
+    fn invalid_codes() {
+        assert!(matches!(RunCode::decode(""), Err(RunCodeError::NotACode)));
+        assert!(matches!(
+            RunCode::decode("not base32!"),
+            Err(RunCodeError::NotACode)
+        ));
+        let unknown_format = BASE32_NOPAD.encode(&[FORMAT_VERSION + 1, 0, 0]);
+        assert!(matches!(
+            RunCode::decode(&unknown_format),
+            Err(RunCodeError::UnsupportedFormat(_))
+        ));
+        let truncated = BASE32_NOPAD.encode(&[FORMAT_VERSION, 1]);
+        assert!(matches!(
+            RunCode::decode(&truncated),
+            Err(RunCodeError::Invalid(_))
+        ));
+        let future = RunCode::new(
+            GENERATOR_VERSION + 1,
+            0,
+            &MapConfiguration::default(),
+            &RoomChanceWeights::default(),
+        );
+        assert!(matches!(
+            RunCode::decode(&future.encode()),
+            Err(RunCodeError::UnsupportedGenerator(_))
+        ));
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+
+use crate::{map::RoomId, poisson::distance_squared};
+
+type IndexedRoom = (RoomId, (f32, f32));
+
+/// Uniform grid over room positions, queries only visit the cells overlapping their radius.
+#[derive(Clone, Debug)]
+pub struct SpatialIndex {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<IndexedRoom>>,
+}
+
+impl SpatialIndex {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::default(),
+        }
+    }
+
+    pub fn insert(&mut self, id: RoomId, position: (f32, f32)) {
+        self.cells
+            .entry(self.cell(&position))
+            .or_default()
+            .push((id, position));
+    }
+
+    pub fn remove(&mut self, id: RoomId, position: (f32, f32)) {
+        let cell = self.cell(&position);
+        if let Some(rooms) = self.cells.get_mut(&cell) {
+            rooms.retain(|(r, _)| *r != id);
+            if rooms.is_empty() {
+                self.cells.remove(&cell);
+            }
+        }
+    }
+
+    /// Rooms closer than `radius` to `point`, with their squared distance.
+    pub fn within_radius(&self, point: &(f32, f32), radius: f32) -> Vec<(RoomId, f32)> {
+        let mut result = vec![];
+        self.visit(point, radius, |id, dist_sqrd| result.push((id, dist_sqrd)));
+        result
+    }
+
+    /// Closest room closer than `max_distance` to `point` accepted by `filter`,
+    /// with its squared distance.
+    pub fn nearest(
+        &self,
+        point: &(f32, f32),
+        max_distance: f32,
+        filter: impl Fn(RoomId) -> bool,
+    ) -> Option<(RoomId, f32)> {
+        let mut closest: Option<(RoomId, f32)> = None;
+        self.visit(point, max_distance, |id, dist_sqrd| {
+            if !filter(id) {
+                return;
+            }
+            if !matches!(closest, Some((_, d)) if d <= dist_sqrd) {
+                closest = Some((id, dist_sqrd));
+            }
+        });
+        closest
+    }
+
+    /// Room under `point`, for rooms displayed with a radius of `pick_radius`.
+    pub fn pick(&self, point: &(f32, f32), pick_radius: f32) -> Option<RoomId> {
+        self.nearest(point, pick_radius, |_| true).map(|(id, _)| id)
+    }
+
+    fn visit(&self, point: &(f32, f32), radius: f32, mut visitor: impl FnMut(RoomId, f32)) {
+        let min = self.cell(&(point.0 - radius, point.1 - radius));
+        let max = self.cell(&(point.0 + radius, point.1 + radius));
+        let radius_squared = radius * radius;
+        for x in min.0..=max.0 {
+            for y in min.1..=max.1 {
+                if let Some(rooms) = self.cells.get(&(x, y)) {
+                    for (id, position) in rooms.iter() {
+                        let dist_sqrd = distance_squared(position, point);
+                        if dist_sqrd < radius_squared {
+                            visitor(*id, dist_sqrd);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn cell(&self, point: &(f32, f32)) -> (i32, i32) {
+        (
+            (point.0 / self.cell_size).floor() as i32,
+            (point.1 / self.cell_size).floor() as i32,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn index() -> SpatialIndex {
+        let mut index = SpatialIndex::new(10f32);
+        index.insert(RoomId(0), (0f32, 0f32));
+        index.insert(RoomId(1), (12f32, 0f32));
+        index.insert(RoomId(2), (-25f32, 3f32));
+        index
+    }
+
+    #[test]
+    fn nearest_respects_filter_and_distance() {
+        let index = index();
+        assert_eq!(
+            index.nearest(&(8f32, 0f32), 30f32, |_| true).map(|r| r.0),
+            Some(RoomId(1))
+        );
+        assert_eq!(
+            index
+                .nearest(&(8f32, 0f32), 30f32, |id| id != RoomId(1))
+                .map(|r| r.0),
+            Some(RoomId(0))
+        );
+        assert_eq!(index.nearest(&(100f32, 0f32), 30f32, |_| true), None);
+    }
+    #[test]
+    fn within_radius_crosses_cells() {
+        let index = index();
+        let mut found: Vec<RoomId> = index
+            .within_radius(&(-10f32, 0f32), 16f32)
+            .iter()
+            .map(|r| r.0)
+            .collect();
+        found.sort_by_key(|id| id.0);
+        assert_eq!(found, vec![RoomId(0), RoomId(2)]);
+    }
+    #[test]
+    fn pick_and_remove() {
+        let mut index = index();
+        assert_eq!(index.pick(&(13f32, 1f32), 5f32), Some(RoomId(1)));
+        index.remove(RoomId(1), (12f32, 0f32));
+        assert_eq!(index.pick(&(13f32, 1f32), 5f32), None);
+    }
+}
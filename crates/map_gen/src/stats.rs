@@ -0,0 +1,102 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+use crate::map::{MapDef, RoomId};
+
+/// Shape of a map, to compare seeds and generator settings.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MapStats {
+    pub rooms: usize,
+    pub connections: usize,
+    pub rooms_per_type: BTreeMap<String, usize>,
+    /// Number of rooms for each number of connections.
+    pub degrees: BTreeMap<usize, usize>,
+    /// Independent cycles: connections a spanning forest doesn't need.
+    pub cycles: usize,
+    /// Most moves needed between two connected rooms.
+    pub diameter: u32,
+    pub components: usize,
+}
+
+impl MapStats {
+    pub fn compute(map: &MapDef) -> Self {
+        let mut stats = MapStats {
+            rooms: map.len(),
+            ..Default::default()
+        };
+        for (_, room) in map.rooms() {
+            *stats
+                .rooms_per_type
+                .entry(room.room_type.0.clone())
+                .or_default() += 1;
+            *stats.degrees.entry(room.connections().len()).or_default() += 1;
+            stats.connections += room.connections().len();
+        }
+        stats.connections /= 2;
+
+        let mut seen: HashSet<RoomId> = HashSet::default();
+        for (id, _) in map.rooms() {
+            let distances = hop_distances(map, *id);
+            stats.diameter = stats
+                .diameter
+                .max(distances.values().copied().max().unwrap_or(0));
+            if seen.insert(*id) {
+                stats.components += 1;
+                seen.extend(distances.keys());
+            }
+        }
+        stats.cycles = stats.connections + stats.components - stats.rooms;
+        stats
+    }
+}
+
+/// Moves needed to reach each room connected to `from`.
+fn hop_distances(map: &MapDef, from: RoomId) -> HashMap<RoomId, u32> {
+    let mut distances: HashMap<RoomId, u32> = HashMap::default();
+    distances.insert(from, 0);
+    let mut queue = VecDeque::new();
+    queue.push_back(from);
+    while let Some(id) = queue.pop_front() {
+        let distance = distances[&id] + 1;
+        for connection in map.neighbours(id).unwrap_or(&[]) {
+            if !distances.contains_key(connection) {
+                distances.insert(*connection, distance);
+                queue.push_back(*connection);
+            }
+        }
+    }
+    distances
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::map::{Room, RoomType};
+
+    #[test]
+    fn stats_of_a_square_with_a_tail() {
+        // 0 -- 1 -- 4, 1 -- 2 -- 3 -- 0, and 5 alone.
+        let mut map = MapDef::default();
+        for i in 0..6 {
+            let room_type = if i % 2 == 0 {
+                RoomType::safe()
+            } else {
+                RoomType::new("Coins")
+            };
+            map.add_room(RoomId(i), Room::new((i as f32 * 50f32, 0f32), room_type))
+                .unwrap();
+        }
+        for (a, b) in [(0, 1), (1, 2), (2, 3), (3, 0), (1, 4)].iter() {
+            map.connect(RoomId(*a), RoomId(*b)).unwrap();
+        }
+        let stats = MapStats::compute(&map);
+        assert_eq!(stats.rooms, 6);
+        assert_eq!(stats.connections, 5);
+        assert_eq!(stats.rooms_per_type["Safe"], 3);
+        assert_eq!(stats.rooms_per_type["Coins"], 3);
+        let degrees: Vec<(usize, usize)> = stats.degrees.into_iter().collect();
+        assert_eq!(degrees, vec![(0, 1), (1, 1), (2, 3), (3, 1)]);
+        assert_eq!(stats.cycles, 1);
+        assert_eq!(stats.diameter, 3);
+        assert_eq!(stats.components, 2);
+    }
+}
@@ -0,0 +1,180 @@
+//! Generates maps without the game, to look for outliers among many seeds.
+//!
+//! The player is simulated by a random walk preferring rooms not visited yet, the map is
+//! expanded and culled after each move as in the game.
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::process;
+
+use map_gen::{
+    biome::Biomes,
+    configuration::MapConfiguration,
+    culling::cull,
+    export::{to_svg, ExportState},
+    generator::{is_supported_version, RoomFactory, GENERATOR_VERSION},
+    map::{MapDef, RoomId},
+    random::RandomDeterministic,
+    room_definitions::RoomDefinitions,
+    stats::MapStats,
+};
+use rand::{seq::SliceRandom, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+const USAGE: &str = "Usage: seed_explorer <seed> [options]
+
+Options:
+    --count <n>          explore <n> seeds, starting from <seed> (default 1)
+    --expansions <n>     simulated moves of the player (default 20)
+    --config <file>      MapConfiguration in RON (default: the game default)
+    --rooms <file>       room definitions in RON (default: the game default)
+    --svg <file>         write each map as SVG, `{seed}` is replaced by the seed
+    --generator <n>      generator version to reproduce (default: the latest)";
+
+/// Rooms created on each move, as in the game.
+const ROOMS_TO_CREATE_ON_MOVE: u32 = 5;
+
+struct Options {
+    seed: u64,
+    count: u64,
+    expansions: u32,
+    configuration: MapConfiguration,
+    definitions: RoomDefinitions,
+    svg: Option<String>,
+    generator_version: u32,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, Box<dyn Error>> {
+    let seed = args.next().ok_or("missing seed")?.parse()?;
+    let mut options = Options {
+        seed,
+        count: 1,
+        expansions: 20,
+        configuration: MapConfiguration::default(),
+        definitions: RoomDefinitions::default(),
+        svg: None,
+        generator_version: GENERATOR_VERSION,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("missing value for {}", arg));
+        match arg.as_str() {
+            "--count" => options.count = value()?.parse()?,
+            "--expansions" => options.expansions = value()?.parse()?,
+            "--config" => {
+                options.configuration = ron::de::from_str(&std::fs::read_to_string(value()?)?)?
+            }
+            "--rooms" => {
+                options.definitions = ron::de::from_str(&std::fs::read_to_string(value()?)?)?
+            }
+            "--svg" => options.svg = Some(value()?),
+            "--generator" => {
+                options.generator_version = value()?.parse()?;
+                if !is_supported_version(options.generator_version) {
+                    return Err(format!(
+                        "unsupported generator version {}",
+                        options.generator_version
+                    )
+                    .into());
+                }
+            }
+            _ => return Err(format!("unknown option {}", arg).into()),
+        }
+    }
+    Ok(options)
+}
+
+/// The map after `options.expansions` moves, and the room the player ends in.
+fn explore(seed: u64, options: &Options) -> Result<(MapDef, RoomId), Box<dyn Error>> {
+    let chances = options.definitions.chances(ROOMS_TO_CREATE_ON_MOVE)?;
+    let biomes = Biomes::new(&options.definitions, &chances)?;
+    let mut random = RandomDeterministic::new(seed);
+    let connections = options.configuration.connections.strategy();
+    let mut factory = RoomFactory::new(&mut random, &chances)
+        .with_planar_links(options.configuration.planar_links)
+        .with_connections(connections.as_ref(), options.configuration.loop_density)
+        .with_version(options.generator_version)?;
+    if options.configuration.biomes {
+        factory = factory.with_biomes(&biomes);
+    }
+    let mut generator = options.configuration.layout.generator(factory);
+    // Separate from the generator, so the walk doesn't change which rooms are created.
+    let mut walk = ChaCha8Rng::seed_from_u64(seed);
+
+    let mut map = generator.create_map();
+    let mut position = RoomId(0);
+    let mut visited = HashSet::new();
+    visited.insert(position);
+    for _ in 0..options.expansions {
+        let neighbours = map.neighbours(position)?;
+        let unvisited: Vec<RoomId> = neighbours
+            .iter()
+            .filter(|id| !visited.contains(id))
+            .copied()
+            .collect();
+        let choices = if unvisited.is_empty() {
+            neighbours
+        } else {
+            &unvisited
+        };
+        position = match choices.choose(&mut walk) {
+            Some(next) => *next,
+            None => break,
+        };
+        visited.insert(position);
+        generator.expand(&mut map, position)?;
+        if options.configuration.culling.is_enabled() {
+            cull(&mut map, position, &options.configuration.culling)?;
+        }
+    }
+    Ok((map, position))
+}
+
+fn print_stats(seed: u64, stats: &MapStats) {
+    println!("seed {}", seed);
+    println!(
+        "  rooms {}, connections {}, cycles {}, diameter {}, components {}",
+        stats.rooms, stats.connections, stats.cycles, stats.diameter, stats.components
+    );
+    let types: Vec<String> = stats
+        .rooms_per_type
+        .iter()
+        .map(|(name, count)| format!("{} {}", name, count))
+        .collect();
+    println!("  types: {}", types.join(", "));
+    let degrees: Vec<String> = stats
+        .degrees
+        .iter()
+        .map(|(degree, count)| format!("{}: {}", degree, count))
+        .collect();
+    println!("  degrees: {}", degrees.join(", "));
+}
+
+fn run(options: &Options) -> Result<(), Box<dyn Error>> {
+    for seed in options.seed..options.seed.saturating_add(options.count) {
+        let (map, position) = explore(seed, options)?;
+        print_stats(seed, &MapStats::compute(&map));
+        if let Some(path) = &options.svg {
+            let state = ExportState {
+                player: Some(position),
+                danger_zones: vec![],
+            };
+            let path = path.replace("{seed}", &seed.to_string());
+            std::fs::write(&path, to_svg(&map, &options.definitions, &state))?;
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(err) = run(&options) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}